@@ -0,0 +1,386 @@
+use crate::journal::Mutation;
+use crate::store::{json_column, now_millis, to_json, Store};
+use crate::{AppState, AppStateType};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, OptionalExtension, Row, ToSql};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use tauri::State;
+
+/// Operational status of an agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentStatus {
+    Idle,
+    Busy,
+    Error,
+    Offline,
+}
+
+impl AgentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AgentStatus::Idle => "idle",
+            AgentStatus::Busy => "busy",
+            AgentStatus::Error => "error",
+            AgentStatus::Offline => "offline",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "idle" => Some(AgentStatus::Idle),
+            "busy" => Some(AgentStatus::Busy),
+            "error" => Some(AgentStatus::Error),
+            "offline" => Some(AgentStatus::Offline),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for AgentStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for AgentStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        AgentStatus::parse(raw)
+            .ok_or_else(|| FromSqlError::Other(format!("invalid agent status: {raw}").into()))
+    }
+}
+
+/// Connectivity of the LLM backing an agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmStatus {
+    Connected,
+    Disconnected,
+    Error,
+    Unknown,
+}
+
+impl LlmStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LlmStatus::Connected => "connected",
+            LlmStatus::Disconnected => "disconnected",
+            LlmStatus::Error => "error",
+            LlmStatus::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "connected" => Some(LlmStatus::Connected),
+            "disconnected" => Some(LlmStatus::Disconnected),
+            "error" => Some(LlmStatus::Error),
+            "unknown" => Some(LlmStatus::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for LlmStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for LlmStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        LlmStatus::parse(raw)
+            .ok_or_else(|| FromSqlError::Other(format!("invalid LLM status: {raw}").into()))
+    }
+}
+
+/// Agent capability and status record mirroring the frontend `AgentInfoSchema`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub agent_type: String,
+    pub framework: String,
+    pub algorithms: Vec<String>,
+    pub status: AgentStatus,
+    pub last_activity: i64,
+    pub task_queue: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_status: Option<LlmStatus>,
+}
+
+/// Partial update applied by `update_agent`; absent fields are left unchanged.
+/// The LLM fields are cleared by an explicit `null`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentUpdate {
+    pub name: Option<String>,
+    pub framework: Option<String>,
+    pub algorithms: Option<Vec<String>>,
+    pub status: Option<AgentStatus>,
+    pub last_activity: Option<i64>,
+    pub task_queue: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub llm_provider: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub llm_model: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub llm_status: Option<Option<LlmStatus>>,
+}
+
+/// Tells a field set to `null` (`Some(None)`) apart from an absent one
+/// (`None`, through `#[serde(default)]`).
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl AgentInfo {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("agent id must not be empty".to_string());
+        }
+        if self.name.trim().is_empty() {
+            return Err(format!("agent {} has an empty name", self.id));
+        }
+        if self.agent_type.trim().is_empty() {
+            return Err(format!("agent {} has an empty type", self.id));
+        }
+        Ok(())
+    }
+
+    /// Applies `update`, stamping `lastActivity` with the current time when
+    /// the caller did not supply one.
+    pub fn apply(&mut self, update: AgentUpdate) {
+        if let Some(name) = update.name {
+            self.name = name;
+        }
+        if let Some(framework) = update.framework {
+            self.framework = framework;
+        }
+        if let Some(algorithms) = update.algorithms {
+            self.algorithms = algorithms;
+        }
+        if let Some(status) = update.status {
+            self.status = status;
+        }
+        if let Some(task_queue) = update.task_queue {
+            self.task_queue = task_queue;
+        }
+        if let Some(provider) = update.llm_provider {
+            self.llm_provider = provider;
+        }
+        if let Some(model) = update.llm_model {
+            self.llm_model = model;
+        }
+        if let Some(llm_status) = update.llm_status {
+            self.llm_status = llm_status;
+        }
+        self.last_activity = update.last_activity.unwrap_or_else(now_millis);
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(AgentInfo {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            agent_type: row.get("type")?,
+            framework: row.get("framework")?,
+            algorithms: json_column(row, "algorithms")?,
+            status: row.get("status")?,
+            last_activity: row.get("last_activity")?,
+            task_queue: json_column(row, "task_queue")?,
+            llm_provider: row.get("llm_provider")?,
+            llm_model: row.get("llm_model")?,
+            llm_status: row.get("llm_status")?,
+        })
+    }
+}
+
+impl Store {
+    pub fn agents(&self) -> rusqlite::Result<HashMap<String, AgentInfo>> {
+        let mut stmt = self.conn.prepare("SELECT * FROM agents")?;
+        let rows = stmt.query_map([], AgentInfo::from_row)?;
+        rows.map(|agent| agent.map(|agent| (agent.id.clone(), agent)))
+            .collect()
+    }
+
+    pub fn agent(&self, agent_id: &str) -> rusqlite::Result<Option<AgentInfo>> {
+        self.conn
+            .query_row(
+                "SELECT * FROM agents WHERE id = ?1",
+                params![agent_id],
+                AgentInfo::from_row,
+            )
+            .optional()
+    }
+
+    pub fn upsert_agent(&self, agent: &AgentInfo) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO agents
+                (id, name, type, framework, algorithms, status, last_activity,
+                 task_queue, llm_provider, llm_model, llm_status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                type = excluded.type,
+                framework = excluded.framework,
+                algorithms = excluded.algorithms,
+                status = excluded.status,
+                last_activity = excluded.last_activity,
+                task_queue = excluded.task_queue,
+                llm_provider = excluded.llm_provider,
+                llm_model = excluded.llm_model,
+                llm_status = excluded.llm_status",
+            params![
+                agent.id,
+                agent.name,
+                agent.agent_type,
+                agent.framework,
+                to_json(&agent.algorithms)?,
+                agent.status,
+                agent.last_activity,
+                to_json(&agent.task_queue)?,
+                agent.llm_provider,
+                agent.llm_model,
+                agent.llm_status,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Moves an agent's `lastActivity` forward to `timestamp`. Unregistered
+    /// agents are ignored. Applied with every stored event, so activity is
+    /// journaled along with it.
+    pub fn touch_agent(&self, agent_id: &str, timestamp: i64) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE agents SET last_activity = MAX(last_activity, ?2) WHERE id = ?1",
+            params![agent_id, timestamp],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_agent(&self, agent_id: &str) -> rusqlite::Result<()> {
+        self.conn
+            .execute("DELETE FROM agents WHERE id = ?1", params![agent_id])?;
+        Ok(())
+    }
+}
+
+fn update_registered_agent(
+    state: &mut AppState,
+    agent_id: &str,
+    update: AgentUpdate,
+) -> Result<AgentInfo, String> {
+    let mut agent = state
+        .store
+        .agent(agent_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("agent {agent_id} is not registered"))?;
+
+    agent.apply(update);
+    agent.validate()?;
+    state.commit(Mutation::UpsertAgent {
+        agent: agent.clone(),
+    })?;
+    Ok(agent)
+}
+
+#[tauri::command]
+pub fn get_agent_status(state: State<AppStateType>) -> Result<HashMap<String, AgentInfo>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.store.agents().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn register_agent(agent: AgentInfo, state: State<AppStateType>) -> Result<(), String> {
+    agent.validate()?;
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.commit(Mutation::UpsertAgent { agent })
+}
+
+#[tauri::command]
+pub fn unregister_agent(agent_id: String, state: State<AppStateType>) -> Result<(), String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    if app_state
+        .store
+        .agent(&agent_id)
+        .map_err(|e| e.to_string())?
+        .is_none()
+    {
+        return Err(format!("agent {agent_id} is not registered"));
+    }
+    app_state.commit(Mutation::DeleteAgent { agent_id })
+}
+
+#[tauri::command]
+pub fn update_agent(
+    agent_id: String,
+    update: AgentUpdate,
+    state: State<AppStateType>,
+) -> Result<AgentInfo, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    update_registered_agent(&mut app_state, &agent_id, update)
+}
+
+#[tauri::command]
+pub fn update_agent_status(
+    agent_id: String,
+    status: AgentStatus,
+    state: State<AppStateType>,
+) -> Result<AgentInfo, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let update = AgentUpdate {
+        status: Some(status),
+        ..AgentUpdate::default()
+    };
+    update_registered_agent(&mut app_state, &agent_id, update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn agent() -> AgentInfo {
+        serde_json::from_value(json!({
+            "id": "a1",
+            "name": "Analyst",
+            "type": "analysis",
+            "framework": "esaf",
+            "algorithms": [],
+            "status": "idle",
+            "lastActivity": 1,
+            "taskQueue": [],
+            "llmProvider": "openai",
+            "llmModel": "gpt-4o",
+            "llmStatus": "connected",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn update_leaves_absent_fields_and_clears_null_ones() {
+        let mut agent = agent();
+        let update: AgentUpdate =
+            serde_json::from_value(json!({ "llmModel": null, "lastActivity": 5 })).unwrap();
+        agent.apply(update);
+
+        assert_eq!(agent.llm_provider.as_deref(), Some("openai"));
+        assert_eq!(agent.llm_model, None);
+        assert_eq!(agent.llm_status, Some(LlmStatus::Connected));
+        assert_eq!(agent.last_activity, 5);
+    }
+}
@@ -0,0 +1,474 @@
+use crate::event_query::{SortOrder, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::journal::Mutation;
+use crate::store::{json_column, now_millis, to_json, Store};
+use crate::AppStateType;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, OptionalExtension, Row, ToSql};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use tauri::State;
+use uuid::Uuid;
+
+/// Title of a session until its first user message names it.
+pub const DEFAULT_CHAT_TITLE: &str = "New Chat";
+
+/// Speaker of a chat message, mirroring the frontend `ChatMessage.type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatMessageType {
+    User,
+    Agent,
+    System,
+    Conversation,
+    Thinking,
+}
+
+impl ChatMessageType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatMessageType::User => "user",
+            ChatMessageType::Agent => "agent",
+            ChatMessageType::System => "system",
+            ChatMessageType::Conversation => "conversation",
+            ChatMessageType::Thinking => "thinking",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "user" => Some(ChatMessageType::User),
+            "agent" => Some(ChatMessageType::Agent),
+            "system" => Some(ChatMessageType::System),
+            "conversation" => Some(ChatMessageType::Conversation),
+            "thinking" => Some(ChatMessageType::Thinking),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for ChatMessageType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ChatMessageType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        ChatMessageType::parse(raw)
+            .ok_or_else(|| FromSqlError::Other(format!("invalid chat message type: {raw}").into()))
+    }
+}
+
+/// Chat message mirroring the frontend `ChatMessage`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub message_type: ChatMessageType,
+    pub content: String,
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Map<String, Value>>,
+    pub session_id: String,
+}
+
+impl ChatMessage {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("chat message id must not be empty".to_string());
+        }
+        if self.session_id.trim().is_empty() {
+            return Err(format!("chat message {} has no sessionId", self.id));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(ChatMessage {
+            id: row.get("id")?,
+            message_type: row.get("type")?,
+            content: row.get("content")?,
+            timestamp: row.get("timestamp")?,
+            agent_name: row.get("agent_name")?,
+            metadata: json_column(row, "metadata")?,
+            session_id: row.get("session_id")?,
+        })
+    }
+}
+
+/// Chat session mirroring the frontend `ChatSession`. `messageCount` is
+/// derived from the stored messages and ignored on input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSession {
+    pub id: String,
+    pub title: String,
+    pub created: i64,
+    pub last_activity: i64,
+    #[serde(default)]
+    pub message_count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ChatSession {
+    pub fn new(title: String) -> Self {
+        let now = now_millis();
+        ChatSession {
+            id: Uuid::new_v4().to_string(),
+            title,
+            created: now,
+            last_activity: now,
+            message_count: 0,
+            summary: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("chat session id must not be empty".to_string());
+        }
+        if self.title.trim().is_empty() {
+            return Err(format!("chat session {} has an empty title", self.id));
+        }
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(ChatSession {
+            id: row.get("id")?,
+            title: row.get("title")?,
+            created: row.get("created")?,
+            last_activity: row.get("last_activity")?,
+            message_count: row.get("message_count")?,
+            summary: row.get("summary")?,
+            tags: json_column(row, "tags")?,
+        })
+    }
+}
+
+/// Derives a session title from its first user message, as
+/// `PersistentStorageManager.generateChatTitle` does.
+pub fn title_from_message(content: &str) -> String {
+    let title = content
+        .split_whitespace()
+        .take(6)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if title.is_empty() {
+        return DEFAULT_CHAT_TITLE.to_string();
+    }
+    if title.chars().count() > 50 {
+        let truncated: String = title.chars().take(47).collect();
+        return format!("{truncated}...");
+    }
+    title
+}
+
+/// Trims tags and drops empty and repeated ones, keeping the first
+/// occurrence of each.
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty() && seen.insert(tag.clone()))
+        .collect()
+}
+
+/// Page request for `get_chat_messages`, paged like `query_events`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MessageQuery {
+    pub session_id: String,
+    pub order: SortOrder,
+    pub limit: Option<u32>,
+    /// `nextCursor` of the previous page.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePage {
+    pub messages: Vec<ChatMessage>,
+    /// Pass back as `cursor` to fetch the following page; `None` on the last.
+    pub next_cursor: Option<String>,
+}
+
+/// Session columns plus the derived `message_count`, for queries over
+/// `chat_sessions s`.
+const SESSION_COLUMNS: &str =
+    "s.*, (SELECT COUNT(*) FROM chat_messages m WHERE m.session_id = s.id) AS message_count";
+
+impl Store {
+    /// All sessions, most recently active first.
+    pub fn chat_sessions(&self) -> rusqlite::Result<Vec<ChatSession>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SESSION_COLUMNS} FROM chat_sessions s ORDER BY last_activity DESC, id"
+        ))?;
+        let rows = stmt.query_map([], ChatSession::from_row)?;
+        rows.collect()
+    }
+
+    pub fn chat_session(&self, session_id: &str) -> rusqlite::Result<Option<ChatSession>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {SESSION_COLUMNS} FROM chat_sessions s WHERE id = ?1"),
+                params![session_id],
+                ChatSession::from_row,
+            )
+            .optional()
+    }
+
+    pub fn save_chat_session(&self, session: &ChatSession) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO chat_sessions (id, title, created, last_activity, summary, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO UPDATE SET
+                title = excluded.title,
+                created = excluded.created,
+                last_activity = excluded.last_activity,
+                summary = excluded.summary,
+                tags = excluded.tags",
+            params![
+                session.id,
+                session.title,
+                session.created,
+                session.last_activity,
+                session.summary,
+                to_json(&session.tags)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_chat_message(&self, message: &ChatMessage) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO chat_messages
+                (id, session_id, type, content, timestamp, agent_name, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                message.id,
+                message.session_id,
+                message.message_type,
+                message.content,
+                message.timestamp,
+                message.agent_name,
+                to_json(&message.metadata)?,
+            ],
+        )?;
+        self.index_chat_message(message)
+    }
+
+    /// Stores `session` and appends `message` to it. Runs inside the
+    /// transaction opened by [`Store::apply_journaled`].
+    pub fn append_chat_message(
+        &self,
+        session: &ChatSession,
+        message: &ChatMessage,
+    ) -> rusqlite::Result<()> {
+        self.save_chat_session(session)?;
+        self.insert_chat_message(message)
+    }
+
+    pub fn delete_chat_session(&self, session_id: &str) -> rusqlite::Result<()> {
+        self.unindex_chat_session(session_id)?;
+        self.conn.execute(
+            "DELETE FROM chat_sessions WHERE id = ?1",
+            params![session_id],
+        )?;
+        Ok(())
+    }
+
+    /// Messages of one session, in the order they were appended.
+    pub fn chat_session_messages(&self, session_id: &str) -> rusqlite::Result<Vec<ChatMessage>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM chat_messages WHERE session_id = ?1 ORDER BY seq")?;
+        let rows = stmt.query_map(params![session_id], ChatMessage::from_row)?;
+        rows.collect()
+    }
+
+    /// Every message of every session, in the order it was appended.
+    pub fn chat_messages(&self) -> rusqlite::Result<Vec<ChatMessage>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM chat_messages ORDER BY seq")?;
+        let rows = stmt.query_map([], ChatMessage::from_row)?;
+        rows.collect()
+    }
+
+    /// One page of a session's messages, paged by append order.
+    pub fn chat_message_page(&self, query: &MessageQuery) -> Result<MessagePage, String> {
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(|cursor| {
+                cursor
+                    .parse::<i64>()
+                    .map_err(|_| format!("invalid message cursor: {cursor}"))
+            })
+            .transpose()?;
+        let page_size = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let (comparison, direction) = match query.order {
+            SortOrder::Asc => (">", "ASC"),
+            SortOrder::Desc => ("<", "DESC"),
+        };
+
+        // Fetch one extra row to learn whether another page follows.
+        let sql = format!(
+            "SELECT * FROM chat_messages
+             WHERE session_id = ?1 AND (?2 IS NULL OR seq {comparison} ?2)
+             ORDER BY seq {direction} LIMIT ?3"
+        );
+        let mut stmt = self.conn.prepare(&sql).map_err(|e| e.to_string())?;
+        let mut rows = stmt
+            .query_map(
+                params![query.session_id, cursor, i64::from(page_size) + 1],
+                |row| Ok((row.get::<_, i64>("seq")?, ChatMessage::from_row(row)?)),
+            )
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+
+        let next_cursor = if rows.len() > page_size as usize {
+            rows.truncate(page_size as usize);
+            rows.last().map(|(seq, _)| seq.to_string())
+        } else {
+            None
+        };
+
+        Ok(MessagePage {
+            messages: rows.into_iter().map(|(_, message)| message).collect(),
+            next_cursor,
+        })
+    }
+}
+
+fn stored_session(store: &Store, session_id: &str) -> Result<ChatSession, String> {
+    store
+        .chat_session(session_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("chat session {session_id} does not exist"))
+}
+
+#[tauri::command]
+pub fn create_chat_session(
+    title: Option<String>,
+    state: State<AppStateType>,
+) -> Result<ChatSession, String> {
+    let title = title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| DEFAULT_CHAT_TITLE.to_string());
+    let session = ChatSession::new(title);
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.commit(Mutation::SaveChatSession {
+        session: session.clone(),
+    })?;
+    Ok(session)
+}
+
+#[tauri::command]
+pub fn list_chat_sessions(state: State<AppStateType>) -> Result<Vec<ChatSession>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.store.chat_sessions().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_chat_session(
+    session_id: String,
+    state: State<AppStateType>,
+) -> Result<Option<ChatSession>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state
+        .store
+        .chat_session(&session_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Appends `message` to its session, naming an untitled session after its
+/// first user message.
+#[tauri::command]
+pub fn append_chat_message(
+    message: ChatMessage,
+    state: State<AppStateType>,
+) -> Result<ChatSession, String> {
+    message.validate()?;
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let mut session = stored_session(&app_state.store, &message.session_id)?;
+    if session.title == DEFAULT_CHAT_TITLE && message.message_type == ChatMessageType::User {
+        session.title = title_from_message(&message.content);
+    }
+    session.last_activity = session.last_activity.max(message.timestamp);
+
+    app_state.commit(Mutation::AppendChatMessage {
+        session: session.clone(),
+        message,
+    })?;
+    session.message_count += 1;
+    Ok(session)
+}
+
+#[tauri::command]
+pub fn get_chat_messages(
+    query: MessageQuery,
+    state: State<AppStateType>,
+) -> Result<MessagePage, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    stored_session(&app_state.store, &query.session_id)?;
+    app_state.store.chat_message_page(&query)
+}
+
+#[tauri::command]
+pub fn rename_chat_session(
+    session_id: String,
+    title: String,
+    state: State<AppStateType>,
+) -> Result<ChatSession, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let mut session = stored_session(&app_state.store, &session_id)?;
+    session.title = title.trim().to_string();
+    session.validate()?;
+
+    app_state.commit(Mutation::SaveChatSession {
+        session: session.clone(),
+    })?;
+    Ok(session)
+}
+
+#[tauri::command]
+pub fn set_chat_session_tags(
+    session_id: String,
+    tags: Vec<String>,
+    state: State<AppStateType>,
+) -> Result<ChatSession, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let mut session = stored_session(&app_state.store, &session_id)?;
+    session.tags = normalize_tags(tags);
+
+    app_state.commit(Mutation::SaveChatSession {
+        session: session.clone(),
+    })?;
+    Ok(session)
+}
+
+/// Deletes a session together with all of its messages.
+#[tauri::command]
+pub fn delete_chat_session(session_id: String, state: State<AppStateType>) -> Result<(), String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    stored_session(&app_state.store, &session_id)?;
+    app_state.commit(Mutation::DeleteChatSession { session_id })
+}
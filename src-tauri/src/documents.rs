@@ -0,0 +1,461 @@
+use crate::journal::Mutation;
+use crate::search::SearchKind;
+use crate::store::{json_column, now_millis, to_json, Store};
+use crate::AppStateType;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, OptionalExtension, Row, ToSql};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// Directory inside a workspace holding document bodies, named by the
+/// SHA-256 of their content.
+pub const BLOBS_DIR: &str = "blobs";
+
+/// Kind of a library document, mirroring the frontend `DocumentSource.type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentType {
+    File,
+    Url,
+    Text,
+    Analysis,
+}
+
+impl DocumentType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DocumentType::File => "file",
+            DocumentType::Url => "url",
+            DocumentType::Text => "text",
+            DocumentType::Analysis => "analysis",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "file" => Some(DocumentType::File),
+            "url" => Some(DocumentType::Url),
+            "text" => Some(DocumentType::Text),
+            "analysis" => Some(DocumentType::Analysis),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for DocumentType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for DocumentType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        DocumentType::parse(raw)
+            .ok_or_else(|| FromSqlError::Other(format!("invalid document type: {raw}").into()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    pub created: i64,
+    pub last_accessed: i64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// A document with its body, mirroring the frontend `DocumentSource`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSource {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub document_type: DocumentType,
+    pub content: String,
+    pub metadata: DocumentMetadata,
+    #[serde(default)]
+    pub is_selected: bool,
+}
+
+impl DocumentSource {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("document id must not be empty".to_string());
+        }
+        if self.name.trim().is_empty() {
+            return Err(format!("document {} has an empty name", self.id));
+        }
+        Ok(())
+    }
+
+    /// The index entry for this document, with its body replaced by the
+    /// hash it is stored under.
+    pub fn entry(&self) -> DocumentEntry {
+        DocumentEntry {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            document_type: self.document_type,
+            content_hash: content_hash(self.content.as_bytes()),
+            metadata: self.metadata.clone(),
+            is_selected: self.is_selected,
+        }
+    }
+}
+
+/// Index entry of a library document. Bodies live in the blob store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentEntry {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub document_type: DocumentType,
+    pub content_hash: String,
+    pub metadata: DocumentMetadata,
+    pub is_selected: bool,
+}
+
+impl DocumentEntry {
+    pub(crate) fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(DocumentEntry {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            document_type: row.get("type")?,
+            content_hash: row.get("content_hash")?,
+            metadata: DocumentMetadata {
+                size: row.get("size")?,
+                format: row.get("format")?,
+                created: row.get("created")?,
+                last_accessed: row.get("last_accessed")?,
+                tags: json_column(row, "tags")?,
+                description: row.get("description")?,
+            },
+            is_selected: row.get("is_selected")?,
+        })
+    }
+
+    fn with_content(self, content: String) -> DocumentSource {
+        DocumentSource {
+            id: self.id,
+            name: self.name,
+            document_type: self.document_type,
+            content,
+            metadata: self.metadata,
+            is_selected: self.is_selected,
+        }
+    }
+}
+
+/// Hex SHA-256 of `content`, the name of its blob.
+pub fn content_hash(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// Content-addressed files under a workspace's `BLOBS_DIR`. Identical
+/// content is stored once, however many documents refer to it.
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(workspace_dir: &Path) -> Self {
+        BlobStore {
+            dir: workspace_dir.join(BLOBS_DIR),
+        }
+    }
+
+    /// Fans blobs out over subdirectories named by the first two hex digits,
+    /// keeping directories small.
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+
+    /// Stores `content` unless a blob with the same hash exists, returning
+    /// the hash.
+    pub fn put(&self, content: &[u8]) -> Result<String, String> {
+        let hash = content_hash(content);
+        let path = self.path(&hash);
+        if path.exists() {
+            return Ok(hash);
+        }
+
+        let parent = path.parent().expect("blob paths have a parent");
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        let partial = parent.join(format!("{hash}.partial"));
+        fs::write(&partial, content).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Vec<u8>, String> {
+        fs::read(self.path(hash)).map_err(|e| format!("failed to read blob {hash}: {e}"))
+    }
+
+    pub fn delete(&self, hash: &str) -> Result<(), String> {
+        match fs::remove_file(self.path(hash)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes every blob no document refers to, e.g. after the library was
+    /// replaced wholesale.
+    pub fn collect_garbage(&self, store: &Store) -> Result<(), String> {
+        let shards = match fs::read_dir(&self.dir) {
+            Ok(shards) => shards,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        };
+        // Anything other than shard directories and the files in them, such
+        // as a `.DS_Store`, was not put there by the blob store.
+        for shard in shards {
+            let shard = shard.map_err(|e| e.to_string())?;
+            if !shard.file_type().map_err(|e| e.to_string())?.is_dir() {
+                continue;
+            }
+            for blob in fs::read_dir(shard.path()).map_err(|e| e.to_string())? {
+                let blob = blob.map_err(|e| e.to_string())?;
+                if !blob.file_type().map_err(|e| e.to_string())?.is_file() {
+                    continue;
+                }
+                let name = blob.file_name();
+                let Some(hash) = name.to_str() else {
+                    continue;
+                };
+                if !store.blob_in_use(hash).map_err(|e| e.to_string())? {
+                    fs::remove_file(blob.path()).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the body of `entry` back into a full document.
+    pub fn load(&self, entry: DocumentEntry) -> Result<DocumentSource, String> {
+        let content = self.content(&entry)?;
+        Ok(entry.with_content(content))
+    }
+
+    /// The body of `entry`.
+    pub fn content(&self, entry: &DocumentEntry) -> Result<String, String> {
+        String::from_utf8(self.get(&entry.content_hash)?)
+            .map_err(|_| format!("document {} is not valid UTF-8", entry.id))
+    }
+}
+
+impl Store {
+    pub fn documents(&self) -> rusqlite::Result<Vec<DocumentEntry>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM documents ORDER BY created, id")?;
+        let rows = stmt.query_map([], DocumentEntry::from_row)?;
+        rows.collect()
+    }
+
+    pub fn document(&self, document_id: &str) -> rusqlite::Result<Option<DocumentEntry>> {
+        self.conn
+            .query_row(
+                "SELECT * FROM documents WHERE id = ?1",
+                params![document_id],
+                DocumentEntry::from_row,
+            )
+            .optional()
+    }
+
+    /// Saves `document`, reindexing its body from the blob store when the
+    /// body or anything else the index holds changed.
+    pub fn save_document(&self, document: &DocumentEntry) -> rusqlite::Result<()> {
+        let indexed = self.document(&document.id)?.is_some_and(|current| {
+            current.content_hash == document.content_hash
+                && current.name == document.name
+                && current.metadata.created == document.metadata.created
+        });
+        let metadata = &document.metadata;
+        self.conn.execute(
+            "INSERT INTO documents
+                (id, name, type, content_hash, size, format, created, last_accessed,
+                 tags, description, is_selected)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                type = excluded.type,
+                content_hash = excluded.content_hash,
+                size = excluded.size,
+                format = excluded.format,
+                created = excluded.created,
+                last_accessed = excluded.last_accessed,
+                tags = excluded.tags,
+                description = excluded.description,
+                is_selected = excluded.is_selected",
+            params![
+                document.id,
+                document.name,
+                document.document_type,
+                document.content_hash,
+                metadata.size,
+                metadata.format,
+                metadata.created,
+                metadata.last_accessed,
+                to_json(&metadata.tags)?,
+                metadata.description,
+                document.is_selected,
+            ],
+        )?;
+        if !indexed {
+            self.index_document(document)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_document(&self, document_id: &str) -> rusqlite::Result<()> {
+        self.unindex(SearchKind::Document, document_id)?;
+        self.conn
+            .execute("DELETE FROM documents WHERE id = ?1", params![document_id])?;
+        Ok(())
+    }
+
+    /// Whether any document still refers to the blob `hash`.
+    pub fn blob_in_use(&self, hash: &str) -> rusqlite::Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM documents WHERE content_hash = ?1)",
+            params![hash],
+            |row| row.get(0),
+        )
+    }
+}
+
+fn stored_document(store: &Store, document_id: &str) -> Result<DocumentEntry, String> {
+    store
+        .document(document_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("document {document_id} does not exist"))
+}
+
+/// Adds `document` to the library, or replaces the document with the same
+/// id. Its body is written to the blob store before the index is updated.
+#[tauri::command]
+pub fn add_document(
+    mut document: DocumentSource,
+    state: State<AppStateType>,
+) -> Result<DocumentEntry, String> {
+    document.validate()?;
+    document.metadata.last_accessed = now_millis();
+    document
+        .metadata
+        .size
+        .get_or_insert(document.content.len() as u64);
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.quota.check_writable()?;
+    let blobs = BlobStore::new(&app_state.workspaces.active_dir());
+    let replaced = app_state
+        .store
+        .document(&document.id)
+        .map_err(|e| e.to_string())?;
+    blobs.put(document.content.as_bytes())?;
+
+    let entry = document.entry();
+    app_state.commit(Mutation::SaveDocument {
+        document: entry.clone(),
+    })?;
+    if let Some(replaced) = replaced {
+        release_blob(&app_state.store, &blobs, &replaced.content_hash)?;
+    }
+    Ok(entry)
+}
+
+#[tauri::command]
+pub fn get_document(
+    document_id: String,
+    state: State<AppStateType>,
+) -> Result<Option<DocumentSource>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let Some(entry) = app_state
+        .store
+        .document(&document_id)
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(None);
+    };
+    BlobStore::new(&app_state.workspaces.active_dir())
+        .load(entry)
+        .map(Some)
+}
+
+/// Lists the library without document bodies.
+#[tauri::command]
+pub fn list_documents(state: State<AppStateType>) -> Result<Vec<DocumentEntry>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.store.documents().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_document(document_id: String, state: State<AppStateType>) -> Result<(), String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let entry = stored_document(&app_state.store, &document_id)?;
+    app_state.commit(Mutation::DeleteDocument { document_id })?;
+
+    let blobs = BlobStore::new(&app_state.workspaces.active_dir());
+    release_blob(&app_state.store, &blobs, &entry.content_hash)
+}
+
+#[tauri::command]
+pub fn set_document_selection(
+    document_id: String,
+    selected: bool,
+    state: State<AppStateType>,
+) -> Result<DocumentEntry, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let mut entry = stored_document(&app_state.store, &document_id)?;
+    entry.is_selected = selected;
+    entry.metadata.last_accessed = now_millis();
+
+    app_state.commit(Mutation::SaveDocument {
+        document: entry.clone(),
+    })?;
+    Ok(entry)
+}
+
+/// Deletes the blob `hash` once no document refers to it any more.
+fn release_blob(store: &Store, blobs: &BlobStore, hash: &str) -> Result<(), String> {
+    if store.blob_in_use(hash).map_err(|e| e.to_string())? {
+        return Ok(());
+    }
+    blobs.delete(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DATABASE_FILE;
+    use uuid::Uuid;
+
+    #[test]
+    fn garbage_collection_skips_stray_entries() {
+        let dir = std::env::temp_dir().join(format!("esaf-blobs-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let store = Store::open(&dir.join(DATABASE_FILE)).unwrap();
+        let blobs = BlobStore::new(&dir);
+        let hash = blobs.put(b"unreferenced").unwrap();
+        fs::write(dir.join(BLOBS_DIR).join(".DS_Store"), b"").unwrap();
+        fs::create_dir_all(dir.join(BLOBS_DIR).join(&hash[..2]).join("nested")).unwrap();
+
+        blobs.collect_garbage(&store).unwrap();
+
+        assert!(blobs.get(&hash).is_err());
+        assert!(dir.join(BLOBS_DIR).join(".DS_Store").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
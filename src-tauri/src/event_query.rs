@@ -0,0 +1,275 @@
+use crate::events::{ESAFEvent, EventType};
+use crate::store::Store;
+use crate::AppStateType;
+use rusqlite::params_from_iter;
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+/// Page size used when a query does not specify one.
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+
+/// Largest page a single query may request.
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Filters for `query_events`. Every filter is optional; list filters match
+/// any of their values and `since`/`until` bound the timestamp inclusively.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EventQuery {
+    pub types: Vec<EventType>,
+    pub source_agent_ids: Vec<String>,
+    pub task_id: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub order: SortOrder,
+    pub limit: Option<u32>,
+    /// `nextCursor` of the previous page.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventPage {
+    pub events: Vec<ESAFEvent>,
+    /// Pass back as `cursor` to fetch the following page; `None` on the last.
+    pub next_cursor: Option<String>,
+}
+
+impl EventQuery {
+    fn page_size(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn cursor_seq(&self) -> Result<Option<i64>, String> {
+        self.cursor
+            .as_deref()
+            .map(|cursor| {
+                cursor
+                    .parse::<i64>()
+                    .map_err(|_| format!("invalid event cursor: {cursor}"))
+            })
+            .transpose()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                return Err(format!("event query range is empty: {since} > {until}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Store {
+    /// Runs `query` against the persisted event log. Events are paged by their
+    /// storage sequence, which is also publish order.
+    pub fn query_events(&self, query: &EventQuery) -> Result<EventPage, String> {
+        query.validate()?;
+
+        let mut clauses = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if !query.types.is_empty() {
+            clauses.push(format!("type IN ({})", placeholders(query.types.len())));
+            values.extend(
+                query
+                    .types
+                    .iter()
+                    .map(|event_type| Value::Text(event_type.as_str().to_string())),
+            );
+        }
+        if !query.source_agent_ids.is_empty() {
+            clauses.push(format!(
+                "source_agent_id IN ({})",
+                placeholders(query.source_agent_ids.len())
+            ));
+            values.extend(query.source_agent_ids.iter().cloned().map(Value::Text));
+        }
+        if let Some(task_id) = &query.task_id {
+            clauses.push("task_id = ?".to_string());
+            values.push(Value::Text(task_id.clone()));
+        }
+        if let Some(since) = query.since {
+            clauses.push("timestamp >= ?".to_string());
+            values.push(Value::Integer(since));
+        }
+        if let Some(until) = query.until {
+            clauses.push("timestamp <= ?".to_string());
+            values.push(Value::Integer(until));
+        }
+        if let Some(seq) = query.cursor_seq()? {
+            clauses.push(match query.order {
+                SortOrder::Asc => "seq > ?".to_string(),
+                SortOrder::Desc => "seq < ?".to_string(),
+            });
+            values.push(Value::Integer(seq));
+        }
+
+        let filter = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+        let direction = match query.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+
+        // Fetch one extra row to learn whether another page follows.
+        let page_size = query.page_size();
+        values.push(Value::Integer(i64::from(page_size) + 1));
+        let sql = format!("SELECT * FROM events {filter} ORDER BY seq {direction} LIMIT ?");
+
+        let mut stmt = self.conn.prepare(&sql).map_err(|e| e.to_string())?;
+        let mut rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok((row.get::<_, i64>("seq")?, ESAFEvent::from_row(row)?))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+
+        let next_cursor = if rows.len() > page_size as usize {
+            rows.truncate(page_size as usize);
+            rows.last().map(|(seq, _)| seq.to_string())
+        } else {
+            None
+        };
+
+        Ok(EventPage {
+            events: rows.into_iter().map(|(_, event)| event).collect(),
+            next_cursor,
+        })
+    }
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+#[tauri::command]
+pub fn query_events(query: EventQuery, state: State<AppStateType>) -> Result<EventPage, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.store.query_events(&query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DATABASE_FILE;
+    use crate::test_support::temp_dir;
+    use serde_json::Map;
+
+    fn event(id: usize, event_type: EventType, source: &str, task_id: Option<&str>) -> ESAFEvent {
+        ESAFEvent {
+            id: format!("e{id}"),
+            event_type,
+            // Three events share each timestamp.
+            timestamp: 1000 + (id / 3) as i64,
+            source_agent_id: source.to_string(),
+            task_id: task_id.map(str::to_string),
+            payload: Map::new(),
+        }
+    }
+
+    /// Follows `nextCursor` from the first page to the last.
+    fn walk(store: &Store, mut query: EventQuery) -> Vec<String> {
+        let mut ids = Vec::new();
+        loop {
+            let page = store.query_events(&query).unwrap();
+            assert!(page.events.len() <= query.page_size() as usize);
+            ids.extend(page.events.into_iter().map(|event| event.id));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return ids,
+            }
+        }
+    }
+
+    #[test]
+    fn pages_cover_every_event_once_in_either_order() {
+        let dir = temp_dir("events");
+        let store = Store::open(&dir.join(DATABASE_FILE)).unwrap();
+        for id in 0..10 {
+            store
+                .insert_event(&event(id, EventType::TaskCreated, "agent", None))
+                .unwrap();
+        }
+        let all: Vec<String> = (0..10).map(|id| format!("e{id}")).collect();
+
+        for limit in [1, 3, 10, 11] {
+            let query = EventQuery {
+                limit: Some(limit),
+                ..EventQuery::default()
+            };
+            assert_eq!(walk(&store, query.clone()), all, "limit {limit}");
+
+            let query = EventQuery {
+                order: SortOrder::Desc,
+                ..query
+            };
+            let mut newest_first = all.clone();
+            newest_first.reverse();
+            assert_eq!(walk(&store, query), newest_first, "limit {limit}");
+        }
+
+        let query = EventQuery {
+            cursor: Some("not-a-seq".to_string()),
+            ..EventQuery::default()
+        };
+        assert!(store.query_events(&query).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filters_combine() {
+        let dir = temp_dir("events");
+        let store = Store::open(&dir.join(DATABASE_FILE)).unwrap();
+        let events = [
+            event(0, EventType::TaskStarted, "a1", Some("t1")),
+            event(1, EventType::TaskFailed, "a1", Some("t1")),
+            event(2, EventType::TaskFailed, "a2", Some("t1")),
+            event(3, EventType::TaskFailed, "a1", Some("t2")),
+            event(4, EventType::TaskCompleted, "a1", Some("t1")),
+            event(5, EventType::TaskFailed, "a3", Some("t1")),
+            event(6, EventType::TaskFailed, "a1", Some("t1")),
+            event(9, EventType::TaskFailed, "a1", Some("t1")),
+        ];
+        for event in &events {
+            store.insert_event(event).unwrap();
+        }
+
+        let query = EventQuery {
+            types: vec![EventType::TaskFailed, EventType::TaskCompleted],
+            source_agent_ids: vec!["a1".to_string(), "a2".to_string()],
+            task_id: Some("t1".to_string()),
+            since: Some(1000),
+            until: Some(1002),
+            limit: Some(2),
+            ..EventQuery::default()
+        };
+        assert_eq!(walk(&store, query.clone()), ["e1", "e2", "e4", "e6"]);
+
+        let empty = EventQuery {
+            since: Some(1002),
+            until: Some(1001),
+            ..query
+        };
+        assert!(store.query_events(&empty).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
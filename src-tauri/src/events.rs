@@ -0,0 +1,251 @@
+use crate::journal::Mutation;
+use crate::store::{json_column, now_millis, to_json, Store};
+use crate::{AppState, AppStateType};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Row, ToSql};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Name of the Tauri event every published ESAF event is re-emitted under.
+pub const EVENT_CHANNEL: &str = "esaf-event";
+
+/// Number of events a slow backend subscriber may fall behind before it
+/// starts missing them.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Event types published to the Cognitive Substrate, mirroring the frontend
+/// `EventType` enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    TaskCreated,
+    TaskStarted,
+    TaskCompleted,
+    TaskFailed,
+    DataValidated,
+    AnomalyDetected,
+    ConstraintViolation,
+    AgentError,
+    GovernanceVeto,
+}
+
+impl EventType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventType::TaskCreated => "task_created",
+            EventType::TaskStarted => "task_started",
+            EventType::TaskCompleted => "task_completed",
+            EventType::TaskFailed => "task_failed",
+            EventType::DataValidated => "data_validated",
+            EventType::AnomalyDetected => "anomaly_detected",
+            EventType::ConstraintViolation => "constraint_violation",
+            EventType::AgentError => "agent_error",
+            EventType::GovernanceVeto => "governance_veto",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "task_created" => Some(EventType::TaskCreated),
+            "task_started" => Some(EventType::TaskStarted),
+            "task_completed" => Some(EventType::TaskCompleted),
+            "task_failed" => Some(EventType::TaskFailed),
+            "data_validated" => Some(EventType::DataValidated),
+            "anomaly_detected" => Some(EventType::AnomalyDetected),
+            "constraint_violation" => Some(EventType::ConstraintViolation),
+            "agent_error" => Some(EventType::AgentError),
+            "governance_veto" => Some(EventType::GovernanceVeto),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for EventType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for EventType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        EventType::parse(raw)
+            .ok_or_else(|| FromSqlError::Other(format!("invalid event type: {raw}").into()))
+    }
+}
+
+/// Event structure mirroring the frontend `ESAFEventSchema`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ESAFEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: EventType,
+    pub timestamp: i64,
+    pub source_agent_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    pub payload: Map<String, Value>,
+}
+
+impl ESAFEvent {
+    /// Builds a new event stamped with a fresh id and the current time.
+    pub fn new(
+        event_type: EventType,
+        source_agent_id: impl Into<String>,
+        payload: Map<String, Value>,
+        task_id: Option<String>,
+    ) -> Self {
+        ESAFEvent {
+            id: Uuid::new_v4().to_string(),
+            event_type,
+            timestamp: now_millis(),
+            source_agent_id: source_agent_id.into(),
+            task_id,
+            payload,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.source_agent_id.trim().is_empty() {
+            return Err("event sourceAgentId must not be empty".to_string());
+        }
+        if self
+            .task_id
+            .as_deref()
+            .is_some_and(|id| id.trim().is_empty())
+        {
+            return Err("event taskId must not be empty when present".to_string());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(ESAFEvent {
+            id: row.get("id")?,
+            event_type: row.get("type")?,
+            timestamp: row.get("timestamp")?,
+            source_agent_id: row.get("source_agent_id")?,
+            task_id: row.get("task_id")?,
+            payload: json_column(row, "payload")?,
+        })
+    }
+}
+
+/// Backend side of the Cognitive Substrate. Published events are fanned out
+/// to in-process subscribers here and to every webview as Tauri events.
+pub struct EventBus {
+    sender: broadcast::Sender<ESAFEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(SUBSCRIBER_CAPACITY);
+        EventBus { sender }
+    }
+}
+
+impl EventBus {
+    /// Receives every event published after this call, in publish order.
+    #[allow(dead_code)] // For backend consumers of the substrate.
+    pub fn subscribe(&self) -> broadcast::Receiver<ESAFEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Stores `event` and fans it out. Callers hold the app state lock, which
+    /// keeps storage order and delivery order identical across windows.
+    pub fn publish(
+        &self,
+        app: &AppHandle,
+        state: &mut AppState,
+        event: ESAFEvent,
+    ) -> Result<ESAFEvent, String> {
+        event.validate()?;
+        state.commit(Mutation::InsertEvent {
+            event: event.clone(),
+        })?;
+
+        // Having no backend subscribers is not an error.
+        let _ = self.sender.send(event.clone());
+        app.emit(EVENT_CHANNEL, &event).map_err(|e| e.to_string())?;
+        Ok(event)
+    }
+}
+
+impl Store {
+    pub fn insert_event(&self, event: &ESAFEvent) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO events (id, type, timestamp, source_agent_id, task_id, payload)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                event.id,
+                event.event_type,
+                event.timestamp,
+                event.source_agent_id,
+                event.task_id,
+                to_json(&event.payload)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recent `limit` events (all when `None`), oldest first.
+    pub fn recent_events(&self, limit: Option<u32>) -> rusqlite::Result<Vec<ESAFEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM (SELECT * FROM events ORDER BY seq DESC LIMIT ?1) ORDER BY seq",
+        )?;
+        let limit = limit.map_or(-1, i64::from);
+        let rows = stmt.query_map(params![limit], ESAFEvent::from_row)?;
+        rows.collect()
+    }
+
+    pub fn task_events(&self, task_id: &str) -> rusqlite::Result<Vec<ESAFEvent>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM events WHERE task_id = ?1 ORDER BY seq")?;
+        let rows = stmt.query_map(params![task_id], ESAFEvent::from_row)?;
+        rows.collect()
+    }
+}
+
+#[tauri::command]
+pub fn publish_event(
+    event_type: EventType,
+    source_agent_id: String,
+    payload: Map<String, Value>,
+    task_id: Option<String>,
+    app: AppHandle,
+    bus: State<EventBus>,
+    state: State<AppStateType>,
+) -> Result<ESAFEvent, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let event = ESAFEvent::new(event_type, source_agent_id, payload, task_id);
+    bus.publish(&app, &mut app_state, event)
+}
+
+#[tauri::command]
+pub fn get_event_history(
+    limit: Option<u32>,
+    state: State<AppStateType>,
+) -> Result<Vec<ESAFEvent>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state
+        .store
+        .recent_events(limit)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_task_events(
+    task_id: String,
+    state: State<AppStateType>,
+) -> Result<Vec<ESAFEvent>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state
+        .store
+        .task_events(&task_id)
+        .map_err(|e| e.to_string())
+}
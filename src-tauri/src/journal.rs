@@ -0,0 +1,334 @@
+use crate::agents::AgentInfo;
+use crate::chats::{ChatMessage, ChatSession};
+use crate::documents::DocumentEntry;
+use crate::events::ESAFEvent;
+use crate::lifecycle::TaskTransition;
+use crate::results::AnalysisResult;
+use crate::snapshot::Snapshot;
+use crate::store::Store;
+use crate::tasks::Task;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// File name of the write-ahead journal, stored next to the database.
+pub const JOURNAL_FILE: &str = "journal.log";
+
+/// Number of journal entries after which the journal is compacted.
+const COMPACT_AFTER_ENTRIES: u64 = 1000;
+
+/// A single state change. Every mutating command is expressed as one of
+/// these so it can be journaled before it is applied, and replayed after a
+/// crash. Mutations carry fully resolved values (ids, timestamps, chosen
+/// tasks) so that replaying them is deterministic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Mutation {
+    SaveTransition {
+        task: Task,
+        transition: TaskTransition,
+    },
+    #[serde(rename_all = "camelCase")]
+    RemoveTask {
+        task_id: String,
+        #[serde(default)]
+        removed_at: i64,
+        #[serde(default)]
+        removed_by: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    UndoRemoveTask {
+        task_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    PurgeTasks {
+        task_ids: Vec<String>,
+    },
+    UpsertAgent {
+        agent: AgentInfo,
+    },
+    #[serde(rename_all = "camelCase")]
+    DeleteAgent {
+        agent_id: String,
+    },
+    InsertEvent {
+        event: ESAFEvent,
+    },
+    InsertResult {
+        result: AnalysisResult,
+    },
+    ReplaceSettings {
+        settings: Map<String, Value>,
+    },
+    /// `snapshot` without its document bodies, which are already in the
+    /// blob store under the `contentHash` of `documents`.
+    RestoreSnapshot {
+        snapshot: Box<Snapshot>,
+        documents: Vec<DocumentEntry>,
+    },
+    SaveChatSession {
+        session: ChatSession,
+    },
+    AppendChatMessage {
+        session: ChatSession,
+        message: ChatMessage,
+    },
+    #[serde(rename_all = "camelCase")]
+    DeleteChatSession {
+        session_id: String,
+    },
+    /// Imported transcripts, stored atomically.
+    ImportChatSessions {
+        sessions: Vec<ChatSession>,
+        messages: Vec<ChatMessage>,
+    },
+    /// The body is already in the blob store under `document.contentHash`.
+    SaveDocument {
+        document: DocumentEntry,
+    },
+    #[serde(rename_all = "camelCase")]
+    DeleteDocument {
+        document_id: String,
+    },
+}
+
+impl Mutation {
+    /// Whether the mutation stores new data rather than updating bounded
+    /// state or freeing space. Only these are refused past the hard storage
+    /// quota, so users can always clean up and change settings.
+    pub fn adds_data(&self) -> bool {
+        match self {
+            Mutation::SaveTransition { transition, .. } => transition.from.is_none(),
+            Mutation::InsertEvent { .. }
+            | Mutation::InsertResult { .. }
+            | Mutation::RestoreSnapshot { .. }
+            | Mutation::AppendChatMessage { .. }
+            | Mutation::ImportChatSessions { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub mutation: Mutation,
+}
+
+/// Append-only log of mutations. Each line is `seq<TAB>crc32<TAB>json`; a
+/// line with a bad checksum marks a torn write and ends the readable log.
+pub struct Journal {
+    file: File,
+    next_seq: u64,
+    entries_since_compaction: u64,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, returning it together with the entries
+    /// newer than `applied_seq` that still need to be replayed. A torn tail
+    /// left by a crash is cut off.
+    pub fn open(path: &Path, applied_seq: u64) -> io::Result<(Self, Vec<JournalEntry>)> {
+        let mut entries = Vec::new();
+        let mut valid_len = 0u64;
+        let mut last_seq = applied_seq;
+
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(path)?);
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 0 {
+                let Some(entry) = parse_line(&line) else {
+                    break;
+                };
+                valid_len += line.len() as u64;
+                last_seq = last_seq.max(entry.seq);
+                if entry.seq > applied_seq {
+                    entries.push(entry);
+                }
+                line.clear();
+            }
+        }
+
+        // Appending keeps writes at the end of the file even after
+        // `compact` truncates it.
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        if file.metadata()?.len() != valid_len {
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+
+        let journal = Journal {
+            file,
+            next_seq: last_seq + 1,
+            entries_since_compaction: entries.len() as u64,
+        };
+        Ok((journal, entries))
+    }
+
+    /// Durably appends `mutation`, returning its sequence number.
+    pub fn append(&mut self, mutation: &Mutation) -> io::Result<u64> {
+        let json = serde_json::to_string(mutation)?;
+        let seq = self.next_seq;
+        let line = format!("{seq}\t{:08x}\t{json}\n", crc32fast::hash(json.as_bytes()));
+
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        self.next_seq += 1;
+        self.entries_since_compaction += 1;
+        Ok(seq)
+    }
+
+    pub fn needs_compaction(&self) -> bool {
+        self.entries_since_compaction >= COMPACT_AFTER_ENTRIES
+    }
+
+    /// Folds the journal into the database snapshot: once SQLite has
+    /// checkpointed every applied entry into the main database file, the
+    /// journal no longer carries information and is truncated.
+    pub fn compact(&mut self, store: &Store) -> Result<(), String> {
+        store.checkpoint().map_err(|e| e.to_string())?;
+        self.file.set_len(0).map_err(|e| e.to_string())?;
+        self.file.sync_all().map_err(|e| e.to_string())?;
+        self.entries_since_compaction = 0;
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Option<JournalEntry> {
+    let line = line.strip_suffix('\n')?;
+    let mut parts = line.splitn(3, '\t');
+    let seq = parts.next()?.parse().ok()?;
+    let checksum = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let json = parts.next()?;
+    if crc32fast::hash(json.as_bytes()) != checksum {
+        return None;
+    }
+
+    let mutation = serde_json::from_str(json).ok()?;
+    Some(JournalEntry { seq, mutation })
+}
+
+impl Store {
+    /// Sequence number of the last journal entry applied to the database.
+    pub fn applied_seq(&self) -> rusqlite::Result<u64> {
+        let seq: i64 = self.conn.query_row(
+            "SELECT COALESCE(MAX(applied_seq), 0) FROM journal_state",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(seq as u64)
+    }
+
+    /// Applies `mutation` and records `seq` as applied in one transaction, so
+    /// a crash leaves either both or neither in the database.
+    pub fn apply_journaled(&self, seq: u64, mutation: &Mutation) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.apply(mutation)?;
+        self.set_applied_seq(seq)?;
+        tx.commit()
+    }
+
+    /// Marks `seq` as applied without applying anything, for entries that
+    /// failed and must not be retried.
+    pub fn set_applied_seq(&self, seq: u64) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO journal_state (id, applied_seq) VALUES (0, ?1)
+             ON CONFLICT(id) DO UPDATE SET applied_seq = excluded.applied_seq",
+            params![seq as i64],
+        )?;
+        Ok(())
+    }
+
+    fn apply(&self, mutation: &Mutation) -> rusqlite::Result<()> {
+        match mutation {
+            Mutation::SaveTransition { task, transition } => self.save_transition(task, transition),
+            Mutation::RemoveTask {
+                task_id,
+                removed_at,
+                removed_by,
+            } => self.tombstone_task(task_id, *removed_at, removed_by.as_deref()),
+            Mutation::UndoRemoveTask { task_id } => self.undo_task_removal(task_id),
+            Mutation::PurgeTasks { task_ids } => self.purge_tasks(task_ids),
+            Mutation::UpsertAgent { agent } => self.upsert_agent(agent),
+            Mutation::DeleteAgent { agent_id } => self.delete_agent(agent_id),
+            Mutation::InsertEvent { event } => {
+                self.insert_event(event)?;
+                self.touch_agent(&event.source_agent_id, event.timestamp)
+            }
+            Mutation::InsertResult { result } => self.insert_result(result),
+            Mutation::ReplaceSettings { settings } => self.replace_settings(settings),
+            Mutation::RestoreSnapshot {
+                snapshot,
+                documents,
+            } => self.restore(snapshot, documents),
+            Mutation::SaveChatSession { session } => self.save_chat_session(session),
+            Mutation::AppendChatMessage { session, message } => {
+                self.append_chat_message(session, message)
+            }
+            Mutation::DeleteChatSession { session_id } => self.delete_chat_session(session_id),
+            Mutation::ImportChatSessions { sessions, messages } => {
+                self.import_chat_sessions(sessions, messages)
+            }
+            Mutation::SaveDocument { document } => self.save_document(document),
+            Mutation::DeleteDocument { document_id } => self.delete_document(document_id),
+        }
+    }
+
+    /// Replays journal entries left over from a previous run. An entry that
+    /// no longer applies is reported and skipped rather than blocking startup.
+    pub fn replay(&self, entries: &[JournalEntry]) -> rusqlite::Result<()> {
+        for entry in entries {
+            if let Err(e) = self.apply_journaled(entry.seq, &entry.mutation) {
+                log::warn!("skipping journal entry {}: {e}", entry.seq);
+                self.set_applied_seq(entry.seq)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn checkpoint(&self) -> rusqlite::Result<()> {
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn delete_agent(agent_id: &str) -> Mutation {
+        Mutation::DeleteAgent {
+            agent_id: agent_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn entries_appended_after_compaction_are_replayed() {
+        let dir = std::env::temp_dir().join(format!("esaf-journal-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let store = Store::open(&dir.join("esaf.db")).unwrap();
+        let path = dir.join(JOURNAL_FILE);
+
+        let (mut journal, _) = Journal::open(&path, 0).unwrap();
+        journal.append(&delete_agent("before")).unwrap();
+        journal.compact(&store).unwrap();
+        let seq = journal.append(&delete_agent("after")).unwrap();
+        drop(journal);
+
+        let (_, entries) = Journal::open(&path, 0).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].seq, seq);
+        assert!(
+            matches!(&entries[0].mutation, Mutation::DeleteAgent { agent_id } if agent_id == "after")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+use crate::journal::Mutation;
+use crate::store::{now_millis, Store};
+use crate::tasks::{Task, TaskStatus};
+use crate::{AppState, AppStateType};
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tauri::State;
+
+/// Returns whether a task may move from `from` to `to`.
+pub fn is_allowed(from: TaskStatus, to: TaskStatus) -> bool {
+    use TaskStatus::*;
+
+    matches!(
+        (from, to),
+        (Pending, Assigned)
+            | (Pending, Running)
+            | (Pending, Failed)
+            | (Assigned, Running)
+            | (Assigned, Pending)
+            | (Assigned, Failed)
+            | (Running, Completed)
+            | (Running, Failed)
+            | (Failed, Pending)
+    )
+}
+
+/// One recorded status change of a task. `from` is `None` for the entry
+/// written when the task is created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskTransition {
+    pub task_id: String,
+    pub from: Option<TaskStatus>,
+    pub to: TaskStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub timestamp: i64,
+}
+
+impl TaskTransition {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(TaskTransition {
+            task_id: row.get("task_id")?,
+            from: row.get("from_status")?,
+            to: row.get("to_status")?,
+            agent_id: row.get("agent_id")?,
+            reason: row.get("reason")?,
+            timestamp: row.get("timestamp")?,
+        })
+    }
+}
+
+/// Errors returned by `transition_task`, serialized with a `kind` tag so the
+/// frontend can tell them apart.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TransitionError {
+    #[serde(rename_all = "camelCase")]
+    UnknownTask {
+        task_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    IllegalTransition {
+        task_id: String,
+        from: TaskStatus,
+        to: TaskStatus,
+    },
+    #[serde(rename_all = "camelCase")]
+    MissingAgent {
+        task_id: String,
+    },
+    Storage {
+        message: String,
+    },
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::UnknownTask { task_id } => write!(f, "task {task_id} does not exist"),
+            TransitionError::IllegalTransition { task_id, from, to } => {
+                write!(f, "task {task_id} cannot move from {from} to {to}")
+            }
+            TransitionError::MissingAgent { task_id } => {
+                write!(f, "task {task_id} needs an agent id to be assigned")
+            }
+            TransitionError::Storage { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+impl From<rusqlite::Error> for TransitionError {
+    fn from(error: rusqlite::Error) -> Self {
+        TransitionError::Storage {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<String> for TransitionError {
+    fn from(message: String) -> Self {
+        TransitionError::Storage { message }
+    }
+}
+
+/// Applies a status change to `task` in memory after checking it against the
+/// state machine.
+pub fn transition(
+    task: &mut Task,
+    to: TaskStatus,
+    agent_id: Option<&str>,
+) -> Result<(), TransitionError> {
+    if !is_allowed(task.status, to) {
+        return Err(TransitionError::IllegalTransition {
+            task_id: task.id.clone(),
+            from: task.status,
+            to,
+        });
+    }
+
+    match to {
+        TaskStatus::Pending => task.assigned_agent_id = None,
+        TaskStatus::Assigned => {
+            let agent_id = agent_id.ok_or_else(|| TransitionError::MissingAgent {
+                task_id: task.id.clone(),
+            })?;
+            task.assigned_agent_id = Some(agent_id.to_string());
+        }
+        _ => {
+            if let Some(agent_id) = agent_id {
+                task.assigned_agent_id = Some(agent_id.to_string());
+            }
+        }
+    }
+    task.status = to;
+    Ok(())
+}
+
+impl Store {
+    /// Stores `task` and appends `transition` to its history. Runs inside the
+    /// transaction opened by [`Store::apply_journaled`].
+    pub fn save_transition(
+        &self,
+        task: &Task,
+        transition: &TaskTransition,
+    ) -> rusqlite::Result<()> {
+        self.insert_task(task)?;
+        self.insert_transition(transition)
+    }
+
+    pub fn insert_transition(&self, transition: &TaskTransition) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO task_transitions
+                (task_id, from_status, to_status, agent_id, reason, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                transition.task_id,
+                transition.from,
+                transition.to,
+                transition.agent_id,
+                transition.reason,
+                transition.timestamp,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn task_history(&self, task_id: &str) -> rusqlite::Result<Vec<TaskTransition>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM task_transitions WHERE task_id = ?1 ORDER BY timestamp, id")?;
+        let rows = stmt.query_map(params![task_id], TaskTransition::from_row)?;
+        rows.collect()
+    }
+
+    /// Every recorded transition, in the order it was written.
+    pub fn transitions(&self) -> rusqlite::Result<Vec<TaskTransition>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM task_transitions ORDER BY id")?;
+        let rows = stmt.query_map([], TaskTransition::from_row)?;
+        rows.collect()
+    }
+}
+
+/// Moves a stored task to `to`, recording who did it and why.
+pub fn transition_stored_task(
+    state: &mut AppState,
+    task_id: &str,
+    to: TaskStatus,
+    agent_id: Option<String>,
+    reason: Option<String>,
+) -> Result<Task, TransitionError> {
+    let mut task = state
+        .store
+        .task(task_id)?
+        .ok_or_else(|| TransitionError::UnknownTask {
+            task_id: task_id.to_string(),
+        })?;
+
+    let from = task.status;
+    transition(&mut task, to, agent_id.as_deref())?;
+    let record = TaskTransition {
+        task_id: task.id.clone(),
+        from: Some(from),
+        to,
+        agent_id,
+        reason,
+        timestamp: now_millis(),
+    };
+    state.commit(Mutation::SaveTransition {
+        task: task.clone(),
+        transition: record,
+    })?;
+    Ok(task)
+}
+
+#[tauri::command]
+pub fn transition_task(
+    task_id: String,
+    status: TaskStatus,
+    agent_id: Option<String>,
+    reason: Option<String>,
+    state: State<AppStateType>,
+) -> Result<Task, TransitionError> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    transition_stored_task(&mut app_state, &task_id, status, agent_id, reason)
+}
+
+#[tauri::command]
+pub fn get_task_history(
+    task_id: String,
+    state: State<AppStateType>,
+) -> Result<Vec<TaskTransition>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state
+        .store
+        .task_history(&task_id)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_task, app_state, task, temp_dir};
+
+    #[test]
+    fn illegal_moves_are_rejected_and_history_records_agents() {
+        let root = temp_dir("lifecycle");
+        let mut state = app_state(&root);
+        add_task(&mut state, task("t1", &[]));
+
+        for to in [TaskStatus::Running, TaskStatus::Completed] {
+            transition_stored_task(&mut state, "t1", to, Some("agent-1".to_string()), None)
+                .unwrap();
+        }
+        let error =
+            transition_stored_task(&mut state, "t1", TaskStatus::Running, None, None).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                TransitionError::IllegalTransition {
+                    from: TaskStatus::Completed,
+                    to: TaskStatus::Running,
+                    ..
+                }
+            ),
+            "{error}"
+        );
+        let error =
+            transition_stored_task(&mut state, "t2", TaskStatus::Running, None, None).unwrap_err();
+        assert!(
+            matches!(error, TransitionError::UnknownTask { .. }),
+            "{error}"
+        );
+
+        let history = state.store.task_history("t1").unwrap();
+        let steps: Vec<_> = history
+            .iter()
+            .map(|transition| {
+                (
+                    transition.from,
+                    transition.to,
+                    transition.agent_id.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (None, TaskStatus::Pending, None),
+                (
+                    Some(TaskStatus::Pending),
+                    TaskStatus::Running,
+                    Some("agent-1")
+                ),
+                (
+                    Some(TaskStatus::Running),
+                    TaskStatus::Completed,
+                    Some("agent-1")
+                ),
+            ]
+        );
+        let task = state.store.task("t1").unwrap().unwrap();
+        assert_eq!(task.status, TaskStatus::Completed);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn assigning_needs_an_agent() {
+        let mut task = task("t1", &[]);
+        let error = transition(&mut task, TaskStatus::Assigned, None).unwrap_err();
+        assert!(
+            matches!(error, TransitionError::MissingAgent { .. }),
+            "{error}"
+        );
+        transition(&mut task, TaskStatus::Assigned, Some("agent-1")).unwrap();
+        transition(&mut task, TaskStatus::Pending, None).unwrap();
+        assert_eq!(task.assigned_agent_id, None);
+    }
+}
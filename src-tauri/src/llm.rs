@@ -0,0 +1,1155 @@
+use crate::requests::{RequestOwner, RequestRegistry};
+use crate::settings::{LlmProvider, LlmSettings, ProviderSettings};
+use crate::store::now_millis;
+use crate::streaming::StreamEvent;
+use crate::AppStateType;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+/// Version sent in the `anthropic-version` header.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Longest provider error body quoted in an `LlmError`.
+const MAX_ERROR_LEN: usize = 500;
+
+/// Completion request mirroring the frontend `LLMRequest`. Unset options fall
+/// back to the provider's settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmRequest {
+    /// Id under which `cancel_request` can abort the request; issued by the
+    /// backend when unset.
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// The task and agent the completion is for, named in the cancellation
+    /// event.
+    #[serde(flatten)]
+    pub owner: RequestOwner,
+    pub prompt: String,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub provider: Option<LlmProvider>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenUsage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_tokens: Option<u64>,
+}
+
+/// Completion mirroring the frontend `LLMResponse`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmResponse {
+    pub content: String,
+    pub provider: LlmProvider,
+    pub model: String,
+    pub timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_usage: Option<TokenUsage>,
+    /// Provider-specific details such as the finish reason.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelType {
+    Text,
+    Embedding,
+    Vision,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModelStatus {
+    Available,
+    /// Held in memory by a local server.
+    Loaded,
+}
+
+/// Model description mirroring the frontend `ModelInfo`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    pub provider: LlmProvider,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub model_type: Option<ModelType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameter_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<ModelStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Map<String, Value>>,
+}
+
+/// Errors returned by the LLM commands, serialized with a `kind` tag.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LlmError {
+    /// Neither the vault nor the environment holds the provider's API key.
+    MissingApiKey {
+        provider: LlmProvider,
+    },
+    /// `llm.maxConcurrentRequests` completions are already running.
+    Busy {
+        limit: u32,
+    },
+    /// The provider could not be reached or did not answer in time.
+    Connection {
+        message: String,
+    },
+    /// The provider answered with an error status.
+    Provider {
+        status: u16,
+        message: String,
+    },
+    /// The provider's answer could not be understood.
+    InvalidResponse {
+        message: String,
+    },
+    /// Aborted by `cancel_request`.
+    Cancelled {
+        request_id: String,
+    },
+    Backend {
+        message: String,
+    },
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::MissingApiKey { provider } => {
+                write!(f, "no API key is configured for {}", provider.as_str())
+            }
+            LlmError::Busy { limit } => {
+                write!(f, "maximum of {limit} concurrent LLM requests exceeded")
+            }
+            LlmError::Connection { message } => f.write_str(message),
+            LlmError::Provider { status, message } => write!(f, "HTTP {status}: {message}"),
+            LlmError::InvalidResponse { message } => {
+                write!(f, "invalid provider response: {message}")
+            }
+            LlmError::Cancelled { request_id } => write!(f, "request {request_id} was cancelled"),
+            LlmError::Backend { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            LlmError::InvalidResponse {
+                message: error.to_string(),
+            }
+        } else {
+            LlmError::Connection {
+                message: error.to_string(),
+            }
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LlmError {
+    fn from(error: rusqlite::Error) -> Self {
+        LlmError::Backend {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<String> for LlmError {
+    fn from(message: String) -> Self {
+        LlmError::Backend { message }
+    }
+}
+
+fn invalid(message: &str) -> LlmError {
+    LlmError::InvalidResponse {
+        message: message.to_string(),
+    }
+}
+
+/// Best human-readable message in a provider error body. Every provider
+/// nests it differently.
+fn error_message(body: &str) -> String {
+    let parsed: Option<Value> = serde_json::from_str(body).ok();
+    let message = parsed.as_ref().and_then(|value| {
+        value
+            .pointer("/error/message")
+            .or_else(|| value.get("error"))
+            .or_else(|| value.get("message"))
+            .and_then(Value::as_str)
+    });
+    match message {
+        Some(message) => message.to_string(),
+        None => body.trim().chars().take(MAX_ERROR_LEN).collect(),
+    }
+}
+
+/// Drops absent fields so the metadata carries only what the provider sent.
+pub fn metadata(value: Value) -> Option<Map<String, Value>> {
+    let Value::Object(mut fields) = value else {
+        return None;
+    };
+    fields.retain(|_, value| !value.is_null());
+    Some(fields)
+}
+
+/// One provider's endpoint, model and credentials, resolved from the settings
+/// and the vault.
+pub struct ProviderClient {
+    pub provider: LlmProvider,
+    pub settings: ProviderSettings,
+    pub api_key: Option<Zeroizing<String>>,
+}
+
+impl ProviderClient {
+    pub async fn complete(
+        &self,
+        http: &Client,
+        request: &LlmRequest,
+    ) -> Result<LlmResponse, LlmError> {
+        let response = self
+            .open(self.request(http, request, false)?.timeout(self.timeout()))
+            .await?;
+        match self.provider {
+            LlmProvider::GoogleGenai => Ok(self.google_genai(response.json().await?)),
+            LlmProvider::Openai | LlmProvider::LmStudio => {
+                self.chat_completions(response.json().await?)
+            }
+            LlmProvider::Anthropic => self.anthropic(response.json().await?),
+            LlmProvider::Ollama => self.ollama(response.json().await?),
+        }
+    }
+
+    /// HTTP request completing `request`, asking for the answer as a stream of
+    /// server-sent events, or NDJSON for Ollama, when `stream` is set.
+    pub fn request(
+        &self,
+        http: &Client,
+        request: &LlmRequest,
+        stream: bool,
+    ) -> Result<RequestBuilder, LlmError> {
+        let builder = match self.provider {
+            LlmProvider::GoogleGenai => {
+                let mut body = json!({
+                    "contents": [{ "role": "user", "parts": [{ "text": request.prompt }] }],
+                    "generationConfig": {
+                        "temperature": self.temperature(request),
+                        "maxOutputTokens": self.max_tokens(request),
+                    },
+                });
+                if let Some(system_prompt) = &request.system_prompt {
+                    body["systemInstruction"] = json!({ "parts": [{ "text": system_prompt }] });
+                }
+                let model = &self.settings.model;
+                let url = if stream {
+                    self.url(&format!("models/{model}:streamGenerateContent?alt=sse"))
+                } else {
+                    self.url(&format!("models/{model}:generateContent"))
+                };
+                http.post(url).json(&body)
+            }
+            LlmProvider::Openai | LlmProvider::LmStudio => {
+                let mut body = json!({
+                    "model": self.settings.model,
+                    "messages": Self::messages(request),
+                    "temperature": self.temperature(request),
+                    "max_tokens": self.max_tokens(request),
+                    "stream": stream,
+                });
+                if stream {
+                    body["stream_options"] = json!({ "include_usage": true });
+                }
+                http.post(self.url("chat/completions")).json(&body)
+            }
+            LlmProvider::Anthropic => {
+                let mut body = json!({
+                    "model": self.settings.model,
+                    "max_tokens": self.max_tokens(request),
+                    "temperature": self.temperature(request),
+                    "messages": [{ "role": "user", "content": request.prompt }],
+                    "stream": stream,
+                });
+                if let Some(system_prompt) = &request.system_prompt {
+                    body["system"] = json!(system_prompt);
+                }
+                http.post(self.url("messages")).json(&body)
+            }
+            LlmProvider::Ollama => http.post(self.url("api/chat")).json(&json!({
+                "model": self.settings.model,
+                "messages": Self::messages(request),
+                "stream": stream,
+                "options": {
+                    "temperature": self.temperature(request),
+                    "num_predict": self.max_tokens(request),
+                },
+            })),
+        };
+        self.authorize(builder)
+    }
+
+    /// Adds the provider's credentials to `builder`.
+    pub fn authorize(&self, builder: RequestBuilder) -> Result<RequestBuilder, LlmError> {
+        Ok(match self.provider {
+            LlmProvider::GoogleGenai => builder.header("x-goog-api-key", self.require_key()?),
+            LlmProvider::Openai => builder.bearer_auth(self.require_key()?),
+            // LM Studio speaks the OpenAI API but ignores the key, so it is
+            // only sent when one is configured.
+            LlmProvider::LmStudio => match &self.api_key {
+                Some(api_key) => builder.bearer_auth(api_key.as_str()),
+                None => builder,
+            },
+            LlmProvider::Anthropic => builder
+                .header("x-api-key", self.require_key()?)
+                .header("anthropic-version", ANTHROPIC_VERSION),
+            LlmProvider::Ollama => builder,
+        })
+    }
+
+    /// Sends `builder` and turns an error status into an `LlmError`.
+    pub async fn open(&self, builder: RequestBuilder) -> Result<Response, LlmError> {
+        let response = builder.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(LlmError::Provider {
+                status: status.as_u16(),
+                message: error_message(&body),
+            });
+        }
+        Ok(response)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.settings.timeout_ms)
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.settings.base_url.trim_end_matches('/'))
+    }
+
+    fn require_key(&self) -> Result<&str, LlmError> {
+        self.api_key
+            .as_deref()
+            .map(String::as_str)
+            .ok_or(LlmError::MissingApiKey {
+                provider: self.provider,
+            })
+    }
+
+    fn temperature(&self, request: &LlmRequest) -> f64 {
+        request.temperature.unwrap_or(self.settings.temperature)
+    }
+
+    fn max_tokens(&self, request: &LlmRequest) -> u32 {
+        request.max_tokens.unwrap_or(self.settings.max_tokens)
+    }
+
+    /// Chat messages for providers that accept the system prompt as a message.
+    fn messages(request: &LlmRequest) -> Vec<Value> {
+        let mut messages = Vec::with_capacity(2);
+        if let Some(system_prompt) = &request.system_prompt {
+            messages.push(json!({ "role": "system", "content": system_prompt }));
+        }
+        messages.push(json!({ "role": "user", "content": request.prompt }));
+        messages
+    }
+
+    pub fn response(
+        &self,
+        content: String,
+        token_usage: Option<TokenUsage>,
+        details: Value,
+    ) -> LlmResponse {
+        LlmResponse {
+            content,
+            provider: self.provider,
+            model: self.settings.model.clone(),
+            timestamp: now_millis(),
+            token_usage,
+            metadata: metadata(details),
+        }
+    }
+
+    fn google_genai(&self, completion: GoogleResponse) -> LlmResponse {
+        let content = completion.text();
+        let usage = completion.usage_metadata.unwrap_or_default();
+        let candidate = completion.candidates.into_iter().next();
+        self.response(
+            content,
+            Some(usage.token_usage()),
+            json!({
+                "finishReason": candidate.as_ref().and_then(|c| c.finish_reason.clone()),
+                "safetyRatings": candidate.and_then(|c| c.safety_ratings),
+            }),
+        )
+    }
+
+    fn chat_completions(&self, completion: ChatCompletion) -> Result<LlmResponse, LlmError> {
+        let choice = completion.choices.into_iter().next();
+        let content = choice
+            .as_ref()
+            .and_then(|choice| choice.message.as_ref())
+            .and_then(|message| message.content.clone())
+            .filter(|content| !content.is_empty())
+            .ok_or_else(|| invalid("no content received"))?;
+        Ok(self.response(
+            content,
+            completion.usage.map(ChatUsage::token_usage),
+            json!({
+                "finishReason": choice.and_then(|choice| choice.finish_reason),
+                "id": completion.id,
+                "object": completion.object,
+            }),
+        ))
+    }
+
+    fn anthropic(&self, completion: AnthropicMessage) -> Result<LlmResponse, LlmError> {
+        let content: String = completion
+            .content
+            .iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect();
+        if content.is_empty() {
+            return Err(invalid("no text content received"));
+        }
+        Ok(self.response(
+            content,
+            Some(completion.usage.token_usage()),
+            json!({
+                "id": completion.id,
+                "role": completion.role,
+                "stopReason": completion.stop_reason,
+                "stopSequence": completion.stop_sequence,
+            }),
+        ))
+    }
+
+    fn ollama(&self, completion: OllamaChat) -> Result<LlmResponse, LlmError> {
+        let token_usage = completion.token_usage();
+        let details = completion.details();
+        let content = completion
+            .message
+            .map(|message| message.content)
+            .ok_or_else(|| invalid("no message received"))?;
+        Ok(self.response(content, token_usage, details))
+    }
+}
+
+// Wire formats of the providers. Streamed chunks reuse them; see `streaming`.
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleResponse {
+    #[serde(default)]
+    pub candidates: Vec<GoogleCandidate>,
+    pub usage_metadata: Option<GoogleUsage>,
+}
+
+impl GoogleResponse {
+    /// Text of the first candidate.
+    pub fn text(&self) -> String {
+        self.candidates
+            .first()
+            .and_then(|candidate| candidate.content.as_ref())
+            .map(|content| {
+                content
+                    .parts
+                    .iter()
+                    .filter_map(|part| part.text.as_deref())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleCandidate {
+    pub content: Option<GoogleContent>,
+    pub finish_reason: Option<String>,
+    pub safety_ratings: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct GoogleContent {
+    #[serde(default)]
+    pub parts: Vec<GooglePart>,
+}
+
+#[derive(Deserialize)]
+pub struct GooglePart {
+    pub text: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GoogleUsage {
+    pub prompt_token_count: u64,
+    pub candidates_token_count: u64,
+    pub total_token_count: u64,
+}
+
+impl GoogleUsage {
+    pub fn token_usage(&self) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: Some(self.prompt_token_count),
+            completion_tokens: Some(self.candidates_token_count),
+            total_tokens: Some(self.total_token_count),
+        }
+    }
+}
+
+/// A chat completion, or one chunk of a streamed one.
+#[derive(Deserialize)]
+pub struct ChatCompletion {
+    pub id: Option<String>,
+    pub object: Option<String>,
+    #[serde(default)]
+    pub choices: Vec<ChatChoice>,
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatChoice {
+    pub message: Option<CompletionMessage>,
+    /// Set instead of `message` in streamed chunks.
+    pub delta: Option<CompletionMessage>,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CompletionMessage {
+    pub content: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl ChatUsage {
+    pub fn token_usage(self) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: Some(self.prompt_tokens),
+            completion_tokens: Some(self.completion_tokens),
+            total_tokens: Some(self.total_tokens),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AnthropicMessage {
+    pub id: Option<String>,
+    pub role: Option<String>,
+    #[serde(default)]
+    pub content: Vec<AnthropicBlock>,
+    pub stop_reason: Option<String>,
+    pub stop_sequence: Option<String>,
+    #[serde(default)]
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Deserialize)]
+pub struct AnthropicBlock {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct AnthropicUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl AnthropicUsage {
+    pub fn token_usage(&self) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: Some(self.input_tokens),
+            completion_tokens: Some(self.output_tokens),
+            total_tokens: Some(self.input_tokens + self.output_tokens),
+        }
+    }
+}
+
+/// An Ollama chat answer, or one line of a streamed one. Only the last
+/// line, with `done` set, carries the counters.
+#[derive(Deserialize)]
+pub struct OllamaChat {
+    pub message: Option<OllamaMessage>,
+    #[serde(default)]
+    pub done: bool,
+    pub error: Option<String>,
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_count: Option<u64>,
+    pub eval_count: Option<u64>,
+}
+
+impl OllamaChat {
+    pub fn token_usage(&self) -> Option<TokenUsage> {
+        let (prompt, completion) = (self.prompt_eval_count?, self.eval_count?);
+        Some(TokenUsage {
+            prompt_tokens: Some(prompt),
+            completion_tokens: Some(completion),
+            total_tokens: Some(prompt + completion),
+        })
+    }
+
+    pub fn details(&self) -> Value {
+        json!({
+            "totalDuration": self.total_duration,
+            "loadDuration": self.load_duration,
+            "promptEvalCount": self.prompt_eval_count,
+            "evalCount": self.eval_count,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OllamaMessage {
+    pub content: String,
+}
+
+struct CachedResponse {
+    expires_at: i64,
+    response: LlmResponse,
+}
+
+/// Shared HTTP client and bookkeeping for every completion made by the
+/// backend.
+#[derive(Default)]
+pub struct LlmService {
+    http: Client,
+    active: AtomicU32,
+    cache: Mutex<HashMap<String, CachedResponse>>,
+}
+
+/// Counts a completion against `llm.maxConcurrentRequests` until dropped.
+struct ActiveRequest<'a>(&'a AtomicU32);
+
+impl Drop for ActiveRequest<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl LlmService {
+    pub fn http(&self) -> &Client {
+        &self.http
+    }
+
+    /// Completes `request` with `client`, honouring the caching, concurrency
+    /// and mock settings in `llm`.
+    pub async fn complete(
+        &self,
+        llm: &LlmSettings,
+        client: &ProviderClient,
+        request: &LlmRequest,
+    ) -> Result<LlmResponse, LlmError> {
+        let cache_key = Self::cache_key(client, request);
+        if llm.enable_caching {
+            if let Some(response) = self.cached(&cache_key) {
+                return Ok(response);
+            }
+        }
+
+        let _active = self.begin(llm.max_concurrent_requests)?;
+        let response = deadline(llm, async {
+            if llm.mock_responses {
+                tokio::time::sleep(Duration::from_millis(llm.mock_response_delay_ms)).await;
+                Ok(mock_response(client.provider, request))
+            } else {
+                client.complete(&self.http, request).await
+            }
+        })
+        .await?;
+        self.record(llm, cache_key, &response)?;
+        Ok(response)
+    }
+
+    /// Like `complete`, but reports the completion through `emit` as it
+    /// arrives. Cached and mock completions arrive as a single delta.
+    pub async fn stream(
+        &self,
+        llm: &LlmSettings,
+        client: &ProviderClient,
+        request: &LlmRequest,
+        request_id: &str,
+        emit: impl Fn(StreamEvent) -> Result<(), LlmError>,
+    ) -> Result<LlmResponse, LlmError> {
+        let cache_key = Self::cache_key(client, request);
+        let cached = llm
+            .enable_caching
+            .then(|| self.cached(&cache_key))
+            .flatten();
+        let _active = match cached {
+            Some(_) => None,
+            None => Some(self.begin(llm.max_concurrent_requests)?),
+        };
+
+        emit(StreamEvent::Started {
+            request_id: request_id.to_string(),
+            provider: client.provider,
+            model: client.settings.model.clone(),
+        })?;
+        let whole = |response: LlmResponse| {
+            emit(StreamEvent::Delta {
+                text: response.content.clone(),
+            })?;
+            Ok::<_, LlmError>(response)
+        };
+        let response = match cached {
+            Some(response) => whole(response)?,
+            None => {
+                let response = deadline(llm, async {
+                    if llm.mock_responses {
+                        tokio::time::sleep(Duration::from_millis(llm.mock_response_delay_ms)).await;
+                        whole(mock_response(client.provider, request))
+                    } else {
+                        client
+                            .stream(&self.http, request, |text| {
+                                emit(StreamEvent::Delta {
+                                    text: text.to_string(),
+                                })
+                            })
+                            .await
+                    }
+                })
+                .await?;
+                self.record(llm, cache_key, &response)?;
+                response
+            }
+        };
+
+        if let Some(usage) = &response.token_usage {
+            emit(StreamEvent::Usage {
+                usage: usage.clone(),
+            })?;
+        }
+        emit(StreamEvent::Finished {
+            response: response.clone(),
+        })?;
+        Ok(response)
+    }
+
+    /// Logs a fresh completion and caches it if enabled.
+    fn record(
+        &self,
+        llm: &LlmSettings,
+        cache_key: String,
+        response: &LlmResponse,
+    ) -> Result<(), LlmError> {
+        if llm.debug_logging {
+            log::info!(
+                "LLM response generated: provider={} model={} length={}",
+                response.provider.as_str(),
+                response.model,
+                response.content.len()
+            );
+        }
+
+        if llm.enable_caching {
+            let now = now_millis();
+            let mut cache = self.cache.lock().map_err(|e| e.to_string())?;
+            cache.retain(|_, cached| cached.expires_at > now);
+            cache.insert(
+                cache_key,
+                CachedResponse {
+                    expires_at: now.saturating_add(llm.cache_duration_ms as i64),
+                    response: response.clone(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn begin(&self, limit: u32) -> Result<ActiveRequest<'_>, LlmError> {
+        self.active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (active < limit).then_some(active + 1)
+            })
+            .map_err(|_| LlmError::Busy { limit })?;
+        Ok(ActiveRequest(&self.active))
+    }
+
+    fn cached(&self, key: &str) -> Option<LlmResponse> {
+        let cache = self.cache.lock().ok()?;
+        cache
+            .get(key)
+            .filter(|cached| cached.expires_at > now_millis())
+            .map(|cached| cached.response.clone())
+    }
+
+    /// Identical requests to the same model share a cache entry.
+    fn cache_key(client: &ProviderClient, request: &LlmRequest) -> String {
+        let key = json!({
+            "provider": client.provider,
+            "model": client.settings.model,
+            "prompt": request.prompt,
+            "systemPrompt": request.system_prompt,
+            "temperature": client.temperature(request),
+            "maxTokens": client.max_tokens(request),
+        });
+        hex::encode(Sha256::digest(key.to_string()))
+    }
+}
+
+/// Canned completion used when `llm.mockResponses` is set.
+fn mock_response(provider: LlmProvider, request: &LlmRequest) -> LlmResponse {
+    let excerpt: String = request.prompt.chars().take(50).collect();
+    let topic = if request.prompt.contains("data") {
+        "data analysis"
+    } else {
+        "general information"
+    };
+    let content = format!(
+        "Mock response from {} for prompt: \"{excerpt}...\"\n\n\
+         This is a simulated response for testing purposes. In production, this would be \
+         generated by the actual LLM provider.\n\n\
+         Analysis: The request appears to be asking for {topic}.\n\
+         Confidence: 0.85\n\
+         Reasoning: Mock reasoning based on prompt content.",
+        provider.as_str()
+    );
+    let prompt_len = request.prompt.len() as u64;
+    let content_len = content.len() as u64;
+    LlmResponse {
+        provider,
+        model: "mock-model".to_string(),
+        timestamp: now_millis(),
+        token_usage: Some(TokenUsage {
+            prompt_tokens: Some(prompt_len / 4),
+            completion_tokens: Some(content_len / 4),
+            total_tokens: Some((prompt_len + content_len) / 4),
+        }),
+        metadata: metadata(json!({ "mock": true, "originalProvider": provider })),
+        content,
+    }
+}
+
+/// Fails `work` if it has not finished within `llm.requestTimeoutMs`. The
+/// provider's own timeout only bounds each wait for it, so a slow but steady
+/// answer would otherwise run on indefinitely.
+async fn deadline<T>(
+    llm: &LlmSettings,
+    work: impl Future<Output = Result<T, LlmError>>,
+) -> Result<T, LlmError> {
+    tokio::time::timeout(Duration::from_millis(llm.request_timeout_ms), work)
+        .await
+        .unwrap_or_else(|_| {
+            Err(LlmError::Connection {
+                message: format!(
+                    "LLM request did not finish within {} ms",
+                    llm.request_timeout_ms
+                ),
+            })
+        })
+}
+
+/// Resolves the client for `provider`, or the default one.
+pub fn provider_client(
+    state: &AppStateType,
+    provider: Option<LlmProvider>,
+) -> Result<(LlmSettings, ProviderClient), LlmError> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let llm = app_state.settings()?.settings.llm;
+    let provider = provider.unwrap_or(llm.default_provider);
+    let api_key = if llm.mock_responses {
+        None
+    } else {
+        app_state.vault.provider_api_key(provider)?
+    };
+    let client = ProviderClient {
+        provider,
+        settings: llm.providers.get(provider).clone(),
+        api_key,
+    };
+    Ok((llm, client))
+}
+
+impl LlmRequest {
+    pub fn id(&self) -> String {
+        self.request_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string())
+    }
+}
+
+/// Completes `request` with its provider, or the default one, from the
+/// backend so API keys never reach the webview.
+#[tauri::command]
+pub async fn generate_completion(
+    request: LlmRequest,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+    registry: State<'_, RequestRegistry>,
+) -> Result<LlmResponse, LlmError> {
+    let (llm, client) = provider_client(&state, request.provider)?;
+    let request_id = request.id();
+    let owner = request.owner.clone();
+    let work = async move {
+        let service = app.state::<LlmService>();
+        service.complete(&llm, &client, &request).await
+    };
+    match registry.run(&request_id, owner, work).await? {
+        Some(result) => result,
+        None => Err(LlmError::Cancelled { request_id }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{client, request, run, stub};
+
+    #[test]
+    fn openai_requests_are_authorized_and_mapped() {
+        let (base_url, requests) = stub(vec![(
+            200,
+            r#"{"id":"c1","choices":[{"message":{"content":"hello"},"finish_reason":"stop"}],
+                "usage":{"prompt_tokens":3,"completion_tokens":1,"total_tokens":4}}"#,
+        )]);
+        let client = client(LlmProvider::Openai, &base_url, Some("sk"));
+        let response = run(client.complete(&Client::new(), &request())).unwrap();
+
+        let seen = requests.recv().unwrap();
+        assert!(seen.starts_with("POST /chat/completions "), "{seen}");
+        assert!(seen.to_lowercase().contains("authorization: bearer sk"));
+        assert!(seen.contains(r#""model":"m1""#) && seen.contains(r#""max_tokens":7"#));
+        assert!(
+            seen.contains(r#"{"content":"sys","role":"system"}"#),
+            "{seen}"
+        );
+        assert_eq!(response.content, "hello");
+        assert_eq!(response.provider, LlmProvider::Openai);
+        assert_eq!(response.token_usage.unwrap().total_tokens, Some(4));
+        assert_eq!(response.metadata.unwrap()["finishReason"], "stop");
+    }
+
+    #[test]
+    fn anthropic_requests_are_authorized_and_mapped() {
+        let (base_url, requests) = stub(vec![(
+            200,
+            r#"{"id":"a","content":[{"type":"text","text":"A"},{"type":"text","text":"B"}],
+                "stop_reason":"end_turn","stop_sequence":null,
+                "usage":{"input_tokens":2,"output_tokens":3}}"#,
+        )]);
+        let client = client(LlmProvider::Anthropic, &base_url, Some("k"));
+        let response = run(client.complete(&Client::new(), &request())).unwrap();
+
+        let seen = requests.recv().unwrap();
+        assert!(seen.starts_with("POST /messages "), "{seen}");
+        let head = seen.to_lowercase();
+        assert!(head.contains("x-api-key: k") && head.contains("anthropic-version:"));
+        assert!(seen.contains(r#""system":"sys""#), "{seen}");
+        assert_eq!(response.content, "AB");
+        assert_eq!(response.token_usage.unwrap().total_tokens, Some(5));
+        let metadata = response.metadata.unwrap();
+        assert_eq!(metadata["stopReason"], "end_turn");
+        assert!(!metadata.contains_key("stopSequence"));
+    }
+
+    #[test]
+    fn google_requests_are_authorized_and_mapped() {
+        let (base_url, requests) = stub(vec![(
+            200,
+            r#"{"candidates":[{"content":{"parts":[{"text":"G"}]},"finishReason":"STOP"}],
+                "usageMetadata":{"promptTokenCount":1,"candidatesTokenCount":2,"totalTokenCount":3}}"#,
+        )]);
+        let client = client(
+            LlmProvider::GoogleGenai,
+            &format!("{base_url}/v1beta/"),
+            Some("g"),
+        );
+        let response = run(client.complete(&Client::new(), &request())).unwrap();
+
+        let seen = requests.recv().unwrap();
+        assert!(
+            seen.starts_with("POST /v1beta/models/m1:generateContent "),
+            "{seen}"
+        );
+        assert!(seen.to_lowercase().contains("x-goog-api-key: g"));
+        assert!(seen.contains(r#""maxOutputTokens":7"#), "{seen}");
+        assert_eq!(response.content, "G");
+        assert_eq!(response.token_usage.unwrap().total_tokens, Some(3));
+    }
+
+    #[test]
+    fn local_providers_send_no_credentials() {
+        let (base_url, requests) = stub(vec![(
+            200,
+            r#"{"message":{"role":"assistant","content":"O"},"done":true,
+                "total_duration":9,"prompt_eval_count":4,"eval_count":5}"#,
+        )]);
+        let ollama = client(LlmProvider::Ollama, &base_url, None);
+        let response = run(ollama.complete(&Client::new(), &request())).unwrap();
+
+        let seen = requests.recv().unwrap();
+        assert!(seen.starts_with("POST /api/chat "), "{seen}");
+        assert!(seen.contains(r#""stream":false"#) && seen.contains(r#""num_predict":7"#));
+        assert!(!seen.to_lowercase().contains("authorization"));
+        assert_eq!(response.content, "O");
+        assert_eq!(response.token_usage.unwrap().total_tokens, Some(9));
+
+        let (base_url, requests) =
+            stub(vec![(200, r#"{"choices":[{"message":{"content":"L"}}]}"#)]);
+        let lm_studio = client(LlmProvider::LmStudio, &base_url, None);
+        let response = run(lm_studio.complete(&Client::new(), &request())).unwrap();
+
+        let seen = requests.recv().unwrap();
+        assert!(seen.starts_with("POST /chat/completions "), "{seen}");
+        assert!(!seen.to_lowercase().contains("authorization"));
+        assert_eq!(response.content, "L");
+        assert!(response.token_usage.is_none());
+    }
+
+    #[test]
+    fn hosted_providers_require_an_api_key() {
+        for provider in [
+            LlmProvider::Openai,
+            LlmProvider::Anthropic,
+            LlmProvider::GoogleGenai,
+        ] {
+            let client = client(provider, "http://127.0.0.1:1", None);
+            let error = run(client.complete(&Client::new(), &request())).unwrap_err();
+            assert!(
+                matches!(error, LlmError::MissingApiKey { provider: p } if p == provider),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn error_bodies_become_provider_errors() {
+        let (base_url, _) = stub(vec![
+            (
+                401,
+                r#"{"error":{"type":"authentication_error","message":"bad key"}}"#,
+            ),
+            (404, r#"{"error":"model 'm1' not found"}"#),
+            (500, "upstream exploded"),
+        ]);
+        let http = Client::new();
+        let anthropic = client(LlmProvider::Anthropic, &base_url, Some("k"));
+        let error = run(anthropic.complete(&http, &request())).unwrap_err();
+        assert!(
+            matches!(error, LlmError::Provider { status: 401, ref message } if message == "bad key"),
+            "{error}"
+        );
+        let ollama = client(LlmProvider::Ollama, &base_url, None);
+        let error = run(ollama.complete(&http, &request())).unwrap_err();
+        assert!(
+            matches!(error, LlmError::Provider { status: 404, ref message } if message == "model 'm1' not found"),
+            "{error}"
+        );
+        let error = run(ollama.complete(&http, &request())).unwrap_err();
+        assert!(
+            matches!(error, LlmError::Provider { status: 500, ref message } if message == "upstream exploded"),
+            "{error}"
+        );
+
+        let offline = client(LlmProvider::Ollama, "http://127.0.0.1:1", None);
+        let error = run(offline.complete(&http, &request())).unwrap_err();
+        assert!(matches!(error, LlmError::Connection { .. }), "{error}");
+    }
+
+    #[test]
+    fn service_caches_mocks_and_limits_requests() {
+        let (base_url, _) = stub(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"once"}}]}"#,
+        )]);
+        let client = client(LlmProvider::LmStudio, &base_url, None);
+        let service = LlmService::default();
+        let mut llm = LlmSettings {
+            enable_caching: true,
+            ..LlmSettings::default()
+        };
+        let first = run(service.complete(&llm, &client, &request())).unwrap();
+        let cached = run(service.complete(&llm, &client, &request())).unwrap();
+        assert_eq!(cached.content, "once");
+        assert_eq!(cached.timestamp, first.timestamp);
+
+        llm.enable_caching = false;
+        llm.mock_responses = true;
+        llm.mock_response_delay_ms = 1;
+        let mocked = run(service.complete(&llm, &client, &request())).unwrap();
+        assert_eq!(mocked.model, "mock-model");
+
+        llm.max_concurrent_requests = 0;
+        let error = run(service.complete(&llm, &client, &request())).unwrap_err();
+        assert!(matches!(error, LlmError::Busy { limit: 0 }), "{error}");
+    }
+
+    #[test]
+    fn completions_are_cut_off_at_the_request_timeout() {
+        let client = client(LlmProvider::Openai, "http://127.0.0.1:9", None);
+        let service = LlmService::default();
+        let llm = LlmSettings {
+            request_timeout_ms: 20,
+            mock_responses: true,
+            mock_response_delay_ms: 5000,
+            ..LlmSettings::default()
+        };
+        let timed_out = |error: &LlmError| {
+            matches!(error, LlmError::Connection { message }
+                if message == "LLM request did not finish within 20 ms")
+        };
+
+        let error = run(service.complete(&llm, &client, &request())).unwrap_err();
+        assert!(timed_out(&error), "{error}");
+        let events = Mutex::new(Vec::new());
+        let error = run(service.stream(&llm, &client, &request(), "r1", |event| {
+            events.lock().unwrap().push(event);
+            Ok(())
+        }))
+        .unwrap_err();
+        assert!(timed_out(&error), "{error}");
+        let events = events.into_inner().unwrap();
+        assert!(matches!(events[..], [StreamEvent::Started { .. }]));
+        // Neither completion is still counted as running.
+        assert_eq!(service.active.load(Ordering::SeqCst), 0);
+    }
+}
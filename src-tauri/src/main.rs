@@ -1,21 +1,92 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use serde::{Deserialize, Serialize};
+mod agents;
+mod chats;
+mod documents;
+mod event_query;
+mod events;
+mod journal;
+mod lifecycle;
+mod llm;
+mod migrations;
+mod model_catalog;
+mod ollama;
+mod requests;
+mod results;
+mod scheduler;
+mod search;
+mod settings;
+mod snapshot;
+mod storage;
+mod store;
+mod streaming;
+mod task_graph;
+mod tasks;
+#[cfg(test)]
+mod test_support;
+mod tombstones;
+mod transcripts;
+mod vault;
+mod workspaces;
+
+use events::EventBus;
+use journal::{Journal, Mutation};
+use llm::LlmService;
+use model_catalog::ModelCatalog;
+use requests::RequestRegistry;
+use settings::SettingsFile;
 use std::collections::HashMap;
+use std::fs;
 use std::sync::Mutex;
-use tauri::State;
+use storage::QuotaStatus;
+use store::Store;
+use tauri::{Manager, State};
+use vault::Vault;
+use workspaces::WorkspaceRegistry;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Backend state of the active workspace.
 struct AppState {
-    agents: HashMap<String, String>,
-    tasks: HashMap<String, String>,
+    store: Store,
+    journal: Journal,
+    workspaces: WorkspaceRegistry,
+    vault: Vault,
+    /// Settings shared by all workspaces.
+    settings_file: SettingsFile,
+    catalog: ModelCatalog,
+    /// Disk usage against the quotas, as last measured.
+    quota: QuotaStatus,
+}
+
+impl AppState {
+    /// Journals `mutation` and then applies it to the store. Every change to
+    /// persisted state goes through here.
+    fn commit(&mut self, mutation: Mutation) -> Result<(), String> {
+        if mutation.adds_data() {
+            self.quota.check_writable()?;
+        }
+        let seq = self.journal.append(&mutation).map_err(|e| e.to_string())?;
+        if let Err(e) = self.store.apply_journaled(seq, &mutation) {
+            // The caller sees the failure, so the entry must not be replayed
+            // on the next start either.
+            let _ = self.store.set_applied_seq(seq);
+            return Err(e.to_string());
+        }
+
+        if self.journal.needs_compaction() {
+            self.journal.compact(&self.store)?;
+        }
+        Ok(())
+    }
 }
 
 type AppStateType = Mutex<AppState>;
 
 #[tauri::command]
-fn get_app_info() -> Result<HashMap<String, String>, String> {
+fn get_app_info(state: State<AppStateType>) -> Result<HashMap<String, String>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let workspace = app_state.workspaces.active();
+
     let mut info = HashMap::new();
     info.insert("name".to_string(), "ESAF Framework".to_string());
     info.insert("version".to_string(), "0.1.0".to_string());
@@ -23,64 +94,108 @@ fn get_app_info() -> Result<HashMap<String, String>, String> {
         "description".to_string(),
         "Evolved Synergistic Agentic Framework".to_string(),
     );
+    info.insert("workspaceId".to_string(), workspace.id.clone());
+    info.insert("workspace".to_string(), workspace.name.clone());
     Ok(info)
 }
 
-#[tauri::command]
-fn get_agent_status(state: State<AppStateType>) -> Result<HashMap<String, String>, String> {
-    let app_state = state.lock().map_err(|e| e.to_string())?;
-    Ok(app_state.agents.clone())
-}
-
-#[tauri::command]
-fn update_agent_status(
-    agent_id: String,
-    status: String,
-    state: State<AppStateType>,
-) -> Result<(), String> {
-    let mut app_state = state.lock().map_err(|e| e.to_string())?;
-    app_state.agents.insert(agent_id, status);
-    Ok(())
-}
-
-#[tauri::command]
-fn get_task_list(state: State<AppStateType>) -> Result<HashMap<String, String>, String> {
-    let app_state = state.lock().map_err(|e| e.to_string())?;
-    Ok(app_state.tasks.clone())
-}
-
-#[tauri::command]
-fn add_task(task_id: String, task_data: String, state: State<AppStateType>) -> Result<(), String> {
-    let mut app_state = state.lock().map_err(|e| e.to_string())?;
-    app_state.tasks.insert(task_id, task_data);
-    Ok(())
-}
-
-#[tauri::command]
-fn remove_task(task_id: String, state: State<AppStateType>) -> Result<(), String> {
-    let mut app_state = state.lock().map_err(|e| e.to_string())?;
-    app_state.tasks.remove(&task_id);
-    Ok(())
-}
-
 fn main() {
-    let initial_state = AppState {
-        agents: HashMap::new(),
-        tasks: HashMap::new(),
-    };
-
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
-        .manage(AppStateType::new(initial_state))
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            fs::create_dir_all(&data_dir)?;
+            let workspaces = WorkspaceRegistry::load(&data_dir)?;
+            let (store, journal) = workspaces::open_workspace(&workspaces.active_dir())?;
+            let vault = Vault::load(&data_dir)?;
+            let settings_file = SettingsFile::load(&data_dir)?;
+            let catalog = ModelCatalog::load(&data_dir);
+
+            app.manage(AppStateType::new(AppState {
+                store,
+                journal,
+                workspaces,
+                vault,
+                settings_file,
+                catalog,
+                quota: QuotaStatus::default(),
+            }));
+            app.manage(EventBus::default());
+            app.manage(LlmService::default());
+            app.manage(RequestRegistry::default());
+            tombstones::schedule_purge(app.handle().clone());
+            storage::schedule_quota_checks(app.handle().clone());
+            model_catalog::schedule_catalog_refresh(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_app_info,
-            get_agent_status,
-            update_agent_status,
-            get_task_list,
-            add_task,
-            remove_task
+            agents::get_agent_status,
+            agents::register_agent,
+            agents::unregister_agent,
+            agents::update_agent,
+            agents::update_agent_status,
+            chats::create_chat_session,
+            chats::list_chat_sessions,
+            chats::get_chat_session,
+            chats::append_chat_message,
+            chats::get_chat_messages,
+            chats::rename_chat_session,
+            chats::set_chat_session_tags,
+            chats::delete_chat_session,
+            documents::add_document,
+            documents::get_document,
+            documents::list_documents,
+            documents::delete_document,
+            documents::set_document_selection,
+            event_query::query_events,
+            events::publish_event,
+            events::get_event_history,
+            events::get_task_events,
+            lifecycle::transition_task,
+            lifecycle::get_task_history,
+            llm::generate_completion,
+            model_catalog::get_model_catalog,
+            model_catalog::refresh_model_catalog,
+            ollama::list_ollama_models,
+            ollama::show_ollama_model,
+            ollama::pull_ollama_model,
+            ollama::delete_ollama_model,
+            requests::cancel_request,
+            results::save_analysis_result,
+            results::get_analysis_results,
+            scheduler::get_task_queue,
+            scheduler::claim_next_task,
+            search::search,
+            settings::get_settings,
+            settings::save_settings,
+            snapshot::export_snapshot,
+            snapshot::import_snapshot,
+            storage::get_storage_stats,
+            streaming::stream_completion,
+            tasks::get_task_list,
+            tasks::get_ready_tasks,
+            tasks::get_task_order,
+            tasks::add_task,
+            tasks::remove_task,
+            tombstones::list_removed_tasks,
+            tombstones::undo_remove_task,
+            transcripts::export_chat_sessions,
+            transcripts::import_chat_sessions,
+            vault::get_vault_status,
+            vault::create_vault,
+            vault::unlock_vault,
+            vault::lock_vault,
+            vault::list_secret_names,
+            vault::set_secret,
+            vault::delete_secret,
+            workspaces::list_workspaces,
+            workspaces::create_workspace,
+            workspaces::rename_workspace,
+            workspaces::delete_workspace,
+            workspaces::switch_workspace
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,404 @@
+use crate::settings::upgrade_legacy_settings;
+use crate::store::Store;
+use crate::tasks::Task;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A forward migration from the previous schema version to `version`.
+/// Migrations must tolerate databases created by builds that predate
+/// versioning, which already carry some of their changes.
+struct Migration {
+    version: u32,
+    name: &'static str,
+    apply: fn(&Store) -> rusqlite::Result<()>,
+}
+
+/// Every schema change, oldest first. Append new migrations here; never edit
+/// or reorder released ones.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial schema",
+        apply: Store::initial_schema,
+    },
+    Migration {
+        version: 2,
+        name: "task tombstones",
+        apply: Store::add_tombstone_columns,
+    },
+    Migration {
+        version: 3,
+        name: "chat sessions",
+        apply: Store::create_chat_tables,
+    },
+    Migration {
+        version: 4,
+        name: "document library",
+        apply: Store::create_document_table,
+    },
+    Migration {
+        version: 5,
+        name: "search index",
+        apply: Store::create_search_tables,
+    },
+    Migration {
+        version: 6,
+        name: "typed settings",
+        apply: Store::upgrade_settings,
+    },
+];
+
+/// Schema version written by this build.
+pub fn current_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was written by a newer build and would be damaged by
+    /// this one.
+    NewerSchema {
+        found: u32,
+        supported: u32,
+    },
+    Backup {
+        path: PathBuf,
+        message: String,
+    },
+    Failed {
+        version: u32,
+        name: &'static str,
+        message: String,
+    },
+    Storage(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NewerSchema { found, supported } => write!(
+                f,
+                "database schema version {found} is newer than the supported version {supported}; \
+                 update the application to open it"
+            ),
+            MigrationError::Backup { path, message } => {
+                write!(
+                    f,
+                    "failed to back up the database to {}: {message}",
+                    path.display()
+                )
+            }
+            MigrationError::Failed {
+                version,
+                name,
+                message,
+            } => write!(f, "migration {version} ({name}) failed: {message}"),
+            MigrationError::Storage(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(error: rusqlite::Error) -> Self {
+        MigrationError::Storage(error)
+    }
+}
+
+/// Path of the copy taken before migrating the database at `path` away from
+/// `version`.
+pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}.bak"));
+    PathBuf::from(backup)
+}
+
+impl Store {
+    pub fn schema_version(&self) -> rusqlite::Result<u32> {
+        self.conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+    }
+
+    /// Brings the database at `path` up to [`current_version`], backing up
+    /// the existing file first. Each migration commits together with its
+    /// version stamp, so a failed upgrade resumes where it stopped.
+    pub(crate) fn migrate(&self, path: &Path) -> Result<(), MigrationError> {
+        let found = self.schema_version()?;
+        let supported = current_version();
+        if found > supported {
+            return Err(MigrationError::NewerSchema { found, supported });
+        }
+        if found == supported {
+            return Ok(());
+        }
+
+        if self.has_tables()? {
+            self.backup(&backup_path(path, found))?;
+        }
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version > found) {
+            let failed = |e: rusqlite::Error| MigrationError::Failed {
+                version: migration.version,
+                name: migration.name,
+                message: e.to_string(),
+            };
+            let tx = self.conn.unchecked_transaction().map_err(failed)?;
+            (migration.apply)(self).map_err(failed)?;
+            self.conn
+                .pragma_update(None, "user_version", migration.version)
+                .map_err(failed)?;
+            tx.commit().map_err(failed)?;
+        }
+        Ok(())
+    }
+
+    fn has_tables(&self) -> rusqlite::Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table')",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Writes a consistent copy of the database to `backup`, replacing any
+    /// earlier backup of the same version.
+    fn backup(&self, backup: &Path) -> Result<(), MigrationError> {
+        let failed = |message: String| MigrationError::Backup {
+            path: backup.to_path_buf(),
+            message,
+        };
+        if backup.exists() {
+            fs::remove_file(backup).map_err(|e| failed(e.to_string()))?;
+        }
+        let target = backup
+            .to_str()
+            .ok_or_else(|| failed("path is not valid UTF-8".to_string()))?;
+        self.conn
+            .execute("VACUUM INTO ?1", [target])
+            .map_err(|e| failed(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Version 1: the schema as it stood when versioning was introduced,
+    /// including the upgrades of the pre-SQLite table layouts.
+    fn initial_schema(&self) -> rusqlite::Result<()> {
+        self.upgrade_legacy_tasks()?;
+        self.drop_legacy_agents()?;
+        self.create_initial_schema()
+    }
+
+    fn create_initial_schema(&self) -> rusqlite::Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS agents (
+                id            TEXT PRIMARY KEY,
+                name          TEXT NOT NULL,
+                type          TEXT NOT NULL,
+                framework     TEXT NOT NULL,
+                algorithms    TEXT NOT NULL,
+                status        TEXT NOT NULL,
+                last_activity INTEGER NOT NULL,
+                task_queue    TEXT NOT NULL,
+                llm_provider  TEXT,
+                llm_model     TEXT,
+                llm_status    TEXT
+            );
+            CREATE TABLE IF NOT EXISTS tasks (
+                id                TEXT PRIMARY KEY,
+                type              TEXT NOT NULL,
+                priority          INTEGER NOT NULL,
+                dependencies      TEXT NOT NULL,
+                payload           TEXT NOT NULL,
+                created_at        INTEGER NOT NULL,
+                assigned_agent_id TEXT,
+                status            TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS task_transitions (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                task_id     TEXT NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+                from_status TEXT,
+                to_status   TEXT NOT NULL,
+                agent_id    TEXT,
+                reason      TEXT,
+                timestamp   INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS task_transitions_task_id
+                ON task_transitions (task_id);
+            CREATE TABLE IF NOT EXISTS events (
+                seq             INTEGER PRIMARY KEY AUTOINCREMENT,
+                id              TEXT NOT NULL UNIQUE,
+                type            TEXT NOT NULL,
+                timestamp       INTEGER NOT NULL,
+                source_agent_id TEXT NOT NULL,
+                task_id         TEXT,
+                payload         TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS events_task_id ON events (task_id);
+            CREATE INDEX IF NOT EXISTS events_type ON events (type);
+            CREATE INDEX IF NOT EXISTS events_source_agent_id ON events (source_agent_id);
+            CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+            CREATE TABLE IF NOT EXISTS results (
+                id             TEXT PRIMARY KEY,
+                source_task_id TEXT NOT NULL,
+                agent_id       TEXT NOT NULL,
+                result         TEXT NOT NULL,
+                confidence     REAL NOT NULL,
+                timestamp      INTEGER NOT NULL,
+                metadata       TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS results_source_task_id ON results (source_task_id);
+            CREATE TABLE IF NOT EXISTS settings (
+                key   TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS journal_state (
+                id          INTEGER PRIMARY KEY CHECK (id = 0),
+                applied_seq INTEGER NOT NULL
+            );",
+        )
+    }
+
+    /// Converts the untyped `tasks (id, data)` table into typed rows. Entries
+    /// whose data does not parse as a valid [`Task`] are dropped, since
+    /// `add_task` would reject them today.
+    fn upgrade_legacy_tasks(&self) -> rusqlite::Result<()> {
+        let has_data_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('tasks') WHERE name = 'data'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_data_column {
+            return Ok(());
+        }
+
+        let legacy: Vec<String> = {
+            let mut stmt = self.conn.prepare("SELECT data FROM tasks")?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        self.conn.execute_batch("DROP TABLE tasks")?;
+        self.create_initial_schema()?;
+        for data in legacy {
+            if let Ok(task) = serde_json::from_str::<Task>(&data) {
+                if task.validate().is_ok() {
+                    self.insert_task(&task)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the `agents (id, status)` table of free-form status strings.
+    /// Those rows carry none of the registry fields, and agents register
+    /// themselves again on startup.
+    fn drop_legacy_agents(&self) -> rusqlite::Result<()> {
+        let is_legacy: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('agents'))
+                AND NOT EXISTS (SELECT 1 FROM pragma_table_info('agents') WHERE name = 'name')",
+            [],
+            |row| row.get(0),
+        )?;
+        if is_legacy {
+            self.conn.execute_batch("DROP TABLE agents")?;
+        }
+        Ok(())
+    }
+
+    /// Adds the `removed_at`/`removed_by` columns to task tables created
+    /// before removals became tombstones.
+    fn add_tombstone_columns(&self) -> rusqlite::Result<()> {
+        let has_tombstones: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('tasks') WHERE name = 'removed_at'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_tombstones {
+            self.conn.execute_batch(
+                "ALTER TABLE tasks ADD COLUMN removed_at INTEGER;
+                 ALTER TABLE tasks ADD COLUMN removed_by TEXT;",
+            )?;
+        }
+        Ok(())
+    }
+
+    fn create_chat_tables(&self) -> rusqlite::Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS chat_sessions (
+                id            TEXT PRIMARY KEY,
+                title         TEXT NOT NULL,
+                created       INTEGER NOT NULL,
+                last_activity INTEGER NOT NULL,
+                summary       TEXT,
+                tags          TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS chat_messages (
+                seq        INTEGER PRIMARY KEY AUTOINCREMENT,
+                id         TEXT NOT NULL UNIQUE,
+                session_id TEXT NOT NULL REFERENCES chat_sessions (id) ON DELETE CASCADE,
+                type       TEXT NOT NULL,
+                content    TEXT NOT NULL,
+                timestamp  INTEGER NOT NULL,
+                agent_name TEXT,
+                metadata   TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS chat_messages_session_id
+                ON chat_messages (session_id, seq);",
+        )
+    }
+
+    fn create_document_table(&self) -> rusqlite::Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS documents (
+                id            TEXT PRIMARY KEY,
+                name          TEXT NOT NULL,
+                type          TEXT NOT NULL,
+                content_hash  TEXT NOT NULL,
+                size          INTEGER,
+                format        TEXT,
+                created       INTEGER NOT NULL,
+                last_accessed INTEGER NOT NULL,
+                tags          TEXT NOT NULL,
+                description   TEXT,
+                is_selected   INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS documents_content_hash ON documents (content_hash);",
+        )
+    }
+
+    /// Inverted index over documents, chat messages and analysis results.
+    /// Existing content is indexed by `Store::backfill_search_index` when the
+    /// workspace is opened. `body` is left empty for documents, whose text
+    /// stays in the blob store.
+    fn create_search_tables(&self) -> rusqlite::Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS search_docs (
+                key       TEXT PRIMARY KEY,
+                kind      TEXT NOT NULL,
+                source_id TEXT NOT NULL,
+                parent_id TEXT,
+                title     TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                length    INTEGER NOT NULL,
+                body      TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS search_docs_parent ON search_docs (kind, parent_id);
+            CREATE TABLE IF NOT EXISTS search_postings (
+                term      TEXT NOT NULL,
+                key       TEXT NOT NULL REFERENCES search_docs (key) ON DELETE CASCADE,
+                tf        INTEGER NOT NULL,
+                positions TEXT NOT NULL,
+                PRIMARY KEY (term, key)
+            ) WITHOUT ROWID;
+            CREATE INDEX IF NOT EXISTS search_postings_key ON search_postings (key);",
+        )
+    }
+
+    /// Moves the flat settings keys saved by earlier builds into the typed
+    /// settings layout.
+    fn upgrade_settings(&self) -> rusqlite::Result<()> {
+        let stored = self.stored_settings()?;
+        self.replace_settings(&upgrade_legacy_settings(&stored))
+    }
+}
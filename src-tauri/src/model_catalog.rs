@@ -0,0 +1,569 @@
+use crate::llm::{self, metadata, LlmError, LlmService, ModelInfo, ModelType, ProviderClient};
+use crate::ollama;
+use crate::settings::{LlmProvider, ProviderSettingsMap};
+use crate::store::now_millis;
+use crate::AppStateType;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Model lists of every provider endpoint in the app data directory, shared
+/// by all workspaces.
+pub const CATALOG_FILE: &str = "model-catalog.json";
+
+/// Name of the Tauri event carrying each refreshed `ModelCacheEntry`.
+pub const CATALOG_CHANNEL: &str = "esaf-models";
+
+/// Version 1 kept one entry per provider, whatever its base URL.
+const CATALOG_VERSION: u32 = 2;
+
+/// How long a model list stays fresh, as in the frontend `ModelCache`.
+const MODEL_CACHE_TTL_MS: i64 = 5 * 60 * 1000;
+
+/// How often the background task looks for expired model lists.
+const REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Models of one provider, mirroring the frontend `ModelCacheEntry`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCacheEntry {
+    pub models: Vec<ModelInfo>,
+    /// When `models` were fetched.
+    pub timestamp: i64,
+    /// When the list is due to be fetched again.
+    pub expiry: i64,
+    pub provider: LlmProvider,
+    /// Where `models` were fetched from.
+    pub base_url: String,
+    /// Why the last fetch failed; `models` are then from an earlier one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// On-disk layout of `CATALOG_FILE`.
+#[derive(Serialize, Deserialize)]
+struct CatalogFile {
+    version: u32,
+    /// Keyed by `endpoint`.
+    providers: BTreeMap<String, ModelCacheEntry>,
+}
+
+/// Just the version of `CATALOG_FILE`, read before the rest whose layout
+/// depends on it.
+#[derive(Deserialize)]
+struct CatalogVersion {
+    version: u32,
+}
+
+/// Key of the models `provider` serves at `base_url`. Workspaces can point a
+/// provider at different servers, so lists are kept per server.
+fn endpoint(provider: LlmProvider, base_url: &str) -> String {
+    format!("{} {base_url}", provider.as_str())
+}
+
+/// The last known models of each provider endpoint, kept on disk so they can
+/// be listed while a provider is offline or before it has been asked.
+pub struct ModelCatalog {
+    path: PathBuf,
+    entries: BTreeMap<String, ModelCacheEntry>,
+}
+
+impl ModelCatalog {
+    /// Reads the catalog in `root`. An unreadable catalog is only a cache, so
+    /// it is discarded rather than failing startup.
+    pub fn load(root: &Path) -> Self {
+        let path = root.join(CATALOG_FILE);
+        let entries = match fs::read(&path) {
+            Ok(json) => Self::parse(&json).unwrap_or_else(|e| {
+                log::warn!("discarding {}: {e}", path.display());
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        ModelCatalog { path, entries }
+    }
+
+    fn parse(json: &[u8]) -> Result<BTreeMap<String, ModelCacheEntry>, String> {
+        let CatalogVersion { version } = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        if version != CATALOG_VERSION {
+            return Err(format!(
+                "catalog version {version} is not {CATALOG_VERSION}"
+            ));
+        }
+        let file: CatalogFile = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        Ok(file.providers)
+    }
+
+    fn entry(
+        &self,
+        provider: LlmProvider,
+        providers: &ProviderSettingsMap,
+    ) -> Option<&ModelCacheEntry> {
+        self.entries
+            .get(&endpoint(provider, &providers.get(provider).base_url))
+    }
+
+    /// The entries of the endpoints `providers` are configured with.
+    pub fn entries(&self, providers: &ProviderSettingsMap) -> Vec<ModelCacheEntry> {
+        LlmProvider::ALL
+            .into_iter()
+            .filter_map(|provider| self.entry(provider, providers))
+            .cloned()
+            .collect()
+    }
+
+    /// Providers whose configured endpoint was never fetched or whose list
+    /// has expired at `now`.
+    pub fn stale(&self, providers: &ProviderSettingsMap, now: i64) -> Vec<LlmProvider> {
+        LlmProvider::ALL
+            .into_iter()
+            .filter(|&provider| {
+                self.entry(provider, providers)
+                    .is_none_or(|entry| entry.expiry <= now)
+            })
+            .collect()
+    }
+
+    /// Stores the outcome of fetching `provider`'s models from `base_url` at
+    /// `now`. A failed fetch keeps the models from the last successful one.
+    pub fn record(
+        &mut self,
+        provider: LlmProvider,
+        base_url: &str,
+        fetched: Result<Vec<ModelInfo>, String>,
+        now: i64,
+    ) -> Result<ModelCacheEntry, String> {
+        let key = endpoint(provider, base_url);
+        let previous = self.entries.remove(&key);
+        let entry = match fetched {
+            Ok(models) => ModelCacheEntry {
+                models,
+                timestamp: now,
+                expiry: now + MODEL_CACHE_TTL_MS,
+                provider,
+                base_url: base_url.to_string(),
+                error: None,
+            },
+            Err(error) => {
+                let (models, timestamp) = previous
+                    .map(|entry| (entry.models, entry.timestamp))
+                    .unwrap_or((Vec::new(), now));
+                ModelCacheEntry {
+                    models,
+                    timestamp,
+                    expiry: now + MODEL_CACHE_TTL_MS,
+                    provider,
+                    base_url: base_url.to_string(),
+                    error: Some(error),
+                }
+            }
+        };
+        self.entries.insert(key, entry.clone());
+        self.save()?;
+        Ok(entry)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let file = CatalogFile {
+            version: CATALOG_VERSION,
+            providers: self.entries.clone(),
+        };
+        let json = serde_json::to_vec_pretty(&file).map_err(|e| e.to_string())?;
+        let partial = self.path.with_file_name(format!("{CATALOG_FILE}.partial"));
+        fs::write(&partial, json).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &self.path).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct GoogleModelList {
+    #[serde(default)]
+    models: Vec<GoogleModel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleModel {
+    /// `models/` followed by the id.
+    name: String,
+    version: Option<String>,
+    display_name: Option<String>,
+    description: Option<String>,
+    input_token_limit: Option<u64>,
+    output_token_limit: Option<u64>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+/// Model list of the OpenAI, Anthropic and LM Studio APIs.
+#[derive(Deserialize)]
+struct ModelPage {
+    #[serde(default)]
+    data: Vec<ListedModel>,
+}
+
+#[derive(Deserialize)]
+struct ListedModel {
+    id: String,
+    /// OpenAI and LM Studio.
+    created: Option<i64>,
+    owned_by: Option<String>,
+    /// Anthropic.
+    display_name: Option<String>,
+    created_at: Option<String>,
+}
+
+fn text_model(provider: LlmProvider, id: String) -> ModelInfo {
+    ModelInfo {
+        name: id.clone(),
+        id,
+        display_name: None,
+        provider,
+        model_type: Some(ModelType::Text),
+        context_length: None,
+        max_tokens: None,
+        parameter_size: None,
+        quantization: None,
+        status: None,
+        metadata: None,
+    }
+}
+
+async fn get<T: serde::de::DeserializeOwned>(
+    client: &ProviderClient,
+    http: &Client,
+    path: &str,
+) -> Result<T, LlmError> {
+    let builder = client.authorize(http.get(client.url(path)).timeout(client.timeout()))?;
+    Ok(client.open(builder).await?.json().await?)
+}
+
+/// The models `client`'s provider offers for completions.
+pub async fn fetch_models(
+    client: &ProviderClient,
+    http: &Client,
+) -> Result<Vec<ModelInfo>, LlmError> {
+    let provider = client.provider;
+    Ok(match provider {
+        LlmProvider::GoogleGenai => {
+            let list: GoogleModelList = get(client, http, "models?pageSize=1000").await?;
+            list.models
+                .into_iter()
+                .filter(|model| {
+                    model
+                        .supported_generation_methods
+                        .iter()
+                        .any(|method| method == "generateContent")
+                })
+                .map(|model| {
+                    let id = model
+                        .name
+                        .strip_prefix("models/")
+                        .unwrap_or(&model.name)
+                        .to_string();
+                    ModelInfo {
+                        display_name: model.display_name,
+                        context_length: model.input_token_limit,
+                        max_tokens: model.output_token_limit,
+                        metadata: metadata(json!({
+                            "version": model.version,
+                            "description": model.description,
+                            "supportedMethods": model.supported_generation_methods,
+                        })),
+                        ..text_model(provider, id)
+                    }
+                })
+                .collect()
+        }
+        // OpenAI also lists embedding, image and audio models.
+        LlmProvider::Openai => {
+            let page: ModelPage = get(client, http, "models").await?;
+            page.data
+                .into_iter()
+                .filter(|model| model.id.contains("gpt") || model.id.contains("o1"))
+                .map(|model| ModelInfo {
+                    metadata: metadata(json!({
+                        "created": model.created,
+                        "ownedBy": model.owned_by,
+                    })),
+                    ..text_model(provider, model.id)
+                })
+                .collect()
+        }
+        LlmProvider::Anthropic => {
+            let page: ModelPage = get(client, http, "models?limit=1000").await?;
+            page.data
+                .into_iter()
+                .map(|model| ModelInfo {
+                    display_name: model.display_name,
+                    metadata: metadata(json!({ "createdAt": model.created_at })),
+                    ..text_model(provider, model.id)
+                })
+                .collect()
+        }
+        LlmProvider::LmStudio => {
+            let page: ModelPage = get(client, http, "models").await?;
+            page.data
+                .into_iter()
+                .map(|model| ModelInfo {
+                    metadata: metadata(json!({ "ownedBy": model.owned_by })),
+                    ..text_model(provider, model.id)
+                })
+                .collect()
+        }
+        LlmProvider::Ollama => ollama::list_models(client, http).await?,
+    })
+}
+
+/// Fetches `provider`'s models from its configured endpoint and records
+/// them. The state lock is not held while waiting on the provider, so the
+/// entry is recorded under the endpoint asked even if the settings change
+/// meanwhile. A provider whose client cannot be resolved, e.g. because the
+/// vault is locked, is recorded as failed so it waits out the TTL too.
+async fn fetch_entry(
+    state: &AppStateType,
+    http: &Client,
+    provider: LlmProvider,
+) -> Result<ModelCacheEntry, String> {
+    let (base_url, fetched) = match llm::provider_client(state, Some(provider)) {
+        Ok((_, client)) => {
+            let fetched = fetch_models(&client, http).await;
+            (client.settings.base_url, fetched.map_err(|e| e.to_string()))
+        }
+        Err(e) => {
+            let app_state = state.lock().map_err(|e| e.to_string())?;
+            let settings = app_state.settings().map_err(|e| e.to_string())?.settings;
+            let base_url = settings.llm.providers.get(provider).base_url.clone();
+            (base_url, Err(e.to_string()))
+        }
+    };
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state
+        .catalog
+        .record(provider, &base_url, fetched, now_millis())
+}
+
+/// Refreshes `provider`'s entry and emits it on `CATALOG_CHANNEL`.
+async fn refresh_provider(
+    app: &AppHandle,
+    provider: LlmProvider,
+) -> Result<ModelCacheEntry, String> {
+    let state = app.state::<AppStateType>();
+    let service = app.state::<LlmService>();
+    let entry = fetch_entry(&state, service.http(), provider).await?;
+    app.emit(CATALOG_CHANNEL, &entry)
+        .map_err(|e| e.to_string())?;
+    Ok(entry)
+}
+
+/// Refreshes `providers` side by side, so a slow provider does not hold up
+/// the others.
+pub async fn refresh(app: &AppHandle, providers: Vec<LlmProvider>) -> Vec<ModelCacheEntry> {
+    let tasks: Vec<_> = providers
+        .into_iter()
+        .map(|provider| {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move { refresh_provider(&app, provider).await })
+        })
+        .collect();
+    let mut entries = Vec::new();
+    for task in tasks {
+        match task.await {
+            Ok(Ok(entry)) => entries.push(entry),
+            Ok(Err(e)) => log::warn!("failed to refresh model catalog: {e}"),
+            Err(e) => log::error!("model catalog refresh failed: {e}"),
+        }
+    }
+    entries
+}
+
+/// Providers of the active workspace whose model lists need fetching.
+fn stale_providers(state: &AppStateType) -> Result<Vec<LlmProvider>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let providers = app_state
+        .settings()
+        .map_err(|e| e.to_string())?
+        .settings
+        .llm
+        .providers;
+    Ok(app_state.catalog.stale(&providers, now_millis()))
+}
+
+/// Periodically refetches the expired model lists for as long as the app
+/// runs, starting right away.
+pub fn schedule_catalog_refresh(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(REFRESH_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let state = app.state::<AppStateType>();
+            let stale = match stale_providers(&state) {
+                Ok(stale) => stale,
+                Err(e) => {
+                    log::error!("failed to check the model catalog: {e}");
+                    continue;
+                }
+            };
+            refresh(&app, stale).await;
+        }
+    });
+}
+
+/// The last known models of every provider at the endpoint the active
+/// workspace uses, without contacting any. Entries past their `expiry`, and
+/// endpoints not fetched yet, are being refreshed in the background.
+#[tauri::command]
+pub fn get_model_catalog(state: State<AppStateType>) -> Result<Vec<ModelCacheEntry>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let providers = app_state
+        .settings()
+        .map_err(|e| e.to_string())?
+        .settings
+        .llm
+        .providers;
+    Ok(app_state.catalog.entries(&providers))
+}
+
+/// Refetches the models of `provider`, or of every provider, now.
+#[tauri::command]
+pub async fn refresh_model_catalog(
+    provider: Option<LlmProvider>,
+    app: AppHandle,
+) -> Result<Vec<ModelCacheEntry>, String> {
+    let providers = match provider {
+        Some(provider) => vec![provider],
+        None => LlmProvider::ALL.to_vec(),
+    };
+    Ok(refresh(&app, providers).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, client, run, stub, temp_dir};
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    fn model(id: &str) -> ModelInfo {
+        text_model(LlmProvider::Ollama, id.to_string())
+    }
+
+    #[test]
+    fn lists_are_kept_per_endpoint() {
+        let dir = std::env::temp_dir().join(format!("esaf-catalog-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let home = ProviderSettingsMap::default();
+        let mut office = home.clone();
+        office.ollama.base_url = "http://office:11434".to_string();
+
+        let mut catalog = ModelCatalog::load(&dir);
+        assert_eq!(catalog.stale(&home, 0).len(), LlmProvider::ALL.len());
+        catalog
+            .record(
+                LlmProvider::Ollama,
+                &home.ollama.base_url,
+                Ok(vec![model("home")]),
+                1000,
+            )
+            .unwrap();
+        let failed = catalog
+            .record(
+                LlmProvider::Ollama,
+                &home.ollama.base_url,
+                Err("offline".to_string()),
+                2000,
+            )
+            .unwrap();
+        assert_eq!(failed.models[0].id, "home");
+        assert_eq!(failed.timestamp, 1000);
+        assert_eq!(failed.error.as_deref(), Some("offline"));
+
+        let catalog = ModelCatalog::load(&dir);
+        assert_eq!(catalog.entries(&home)[0].models[0].id, "home");
+        assert!(!catalog.stale(&home, 3000).contains(&LlmProvider::Ollama));
+        assert!(catalog.entries(&office).is_empty());
+        assert!(catalog.stale(&office, 3000).contains(&LlmProvider::Ollama));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn catalogs_of_another_version_are_discarded() {
+        let dir = std::env::temp_dir().join(format!("esaf-catalog-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(CATALOG_FILE),
+            r#"{"version":1,"providers":{"ollama":{"models":[],"timestamp":1,"expiry":2,"provider":"ollama"}}}"#,
+        )
+        .unwrap();
+
+        let catalog = ModelCatalog::load(&dir);
+        assert!(catalog.entries.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fetched_models_are_limited_to_completions() {
+        let http = Client::new();
+        let (base_url, requests) = stub(vec![(
+            200,
+            r#"{"models":[{"name":"models/gemini-pro","displayName":"Gemini Pro","inputTokenLimit":100,
+                "supportedGenerationMethods":["generateContent"]},
+                {"name":"models/embed","supportedGenerationMethods":["embedContent"]}]}"#,
+        )]);
+        let google = client(LlmProvider::GoogleGenai, &base_url, Some("gk"));
+        let models = run(fetch_models(&google, &http)).unwrap();
+        let seen = requests.recv().unwrap().to_lowercase();
+        assert!(seen.starts_with("get /models?pagesize=1000 "), "{seen}");
+        assert!(seen.contains("x-goog-api-key: gk"));
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "gemini-pro");
+        assert_eq!(models[0].context_length, Some(100));
+
+        let (base_url, requests) = stub(vec![(
+            200,
+            r#"{"data":[{"id":"gpt-4o","owned_by":"openai"},{"id":"whisper-1"}]}"#,
+        )]);
+        let openai = client(LlmProvider::Openai, &base_url, Some("sk"));
+        let models = run(fetch_models(&openai, &http)).unwrap();
+        assert!(requests
+            .recv()
+            .unwrap()
+            .to_lowercase()
+            .contains("authorization: bearer sk"));
+        let ids: Vec<_> = models.iter().map(|model| model.id.as_str()).collect();
+        assert_eq!(ids, ["gpt-4o"]);
+
+        let anthropic = client(LlmProvider::Anthropic, "http://127.0.0.1:1", None);
+        let error = run(fetch_models(&anthropic, &http)).unwrap_err();
+        assert!(matches!(error, LlmError::MissingApiKey { .. }), "{error}");
+    }
+
+    #[test]
+    fn unresolvable_providers_are_recorded_as_failed() {
+        let root = temp_dir("catalog");
+        let mut state = app_state(&root);
+        state.vault.create("correct horse battery").unwrap();
+        state.vault.set("anthropic", "ak").unwrap();
+        state.vault.lock();
+        let state = Mutex::new(state);
+
+        let entry = run(fetch_entry(&state, &Client::new(), LlmProvider::Anthropic)).unwrap();
+        assert_eq!(entry.error.as_deref(), Some("the vault is locked"));
+        let app_state = state.lock().unwrap();
+        let providers = app_state.settings().unwrap().settings.llm.providers;
+        assert_eq!(entry.base_url, providers.anthropic.base_url);
+        let stale = app_state.catalog.stale(&providers, entry.timestamp);
+        assert!(!stale.contains(&LlmProvider::Anthropic), "{stale:?}");
+        drop(app_state);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,507 @@
+use crate::llm::{
+    self, metadata, LlmError, LlmService, ModelInfo, ModelStatus, ModelType, ProviderClient,
+};
+use crate::model_catalog;
+use crate::requests::{RequestOwner, RequestRegistry};
+use crate::settings::LlmProvider;
+use crate::streaming::LineBuffer;
+use crate::AppStateType;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
+
+/// Name of the Tauri event carrying the progress of model pulls.
+pub const PULL_CHANNEL: &str = "esaf-ollama-pull";
+
+/// Status of the last line of a successful pull.
+const PULL_SUCCESS: &str = "success";
+
+#[derive(Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize)]
+struct OllamaModel {
+    name: String,
+    size: Option<u64>,
+    digest: Option<String>,
+    modified_at: Option<String>,
+    /// Set by `/api/ps` for loaded models.
+    expires_at: Option<String>,
+    #[serde(default)]
+    details: ModelFamily,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ModelFamily {
+    format: Option<String>,
+    family: Option<String>,
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+impl OllamaModel {
+    fn info(self, status: ModelStatus) -> ModelInfo {
+        ModelInfo {
+            id: self.name.clone(),
+            display_name: Some(self.name.clone()),
+            name: self.name,
+            provider: LlmProvider::Ollama,
+            model_type: Some(ModelType::Text),
+            context_length: None,
+            max_tokens: None,
+            parameter_size: self.details.parameter_size,
+            quantization: self.details.quantization_level,
+            status: Some(status),
+            metadata: metadata(json!({
+                "size": self.size,
+                "digest": self.digest,
+                "family": self.details.family,
+                "format": self.details.format,
+                "modifiedAt": self.modified_at,
+                "expiresAt": self.expires_at,
+            })),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ShowResponse {
+    license: Option<String>,
+    modelfile: Option<String>,
+    parameters: Option<String>,
+    template: Option<String>,
+    #[serde(default)]
+    details: ModelFamily,
+    #[serde(default)]
+    model_info: Map<String, Value>,
+    #[serde(default)]
+    capabilities: Vec<String>,
+    modified_at: Option<String>,
+}
+
+/// Everything Ollama reports about an installed model.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OllamaModelDetails {
+    pub info: ModelInfo,
+    pub license: Option<String>,
+    pub modelfile: Option<String>,
+    pub parameters: Option<String>,
+    pub template: Option<String>,
+    /// What the model supports, such as `completion`, `vision` or `tools`.
+    pub capabilities: Vec<String>,
+    /// Architecture parameters keyed like `llama.context_length`.
+    pub model_info: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct PullLine {
+    #[serde(default)]
+    status: String,
+    digest: Option<String>,
+    total: Option<u64>,
+    completed: Option<u64>,
+    error: Option<String>,
+}
+
+/// Progress of a model pull, as emitted on `PULL_CHANNEL`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullProgress {
+    pub request_id: String,
+    pub model: String,
+    /// Ollama's description of the current step, e.g. `pulling manifest`.
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+    /// Downloaded share of every layer seen so far, from 0 to 100.
+    pub percent: Option<f64>,
+}
+
+/// Bytes downloaded and expected per layer digest. Ollama reports layers one
+/// at a time; the overall percentage covers all of them.
+#[derive(Default)]
+struct PullTracker {
+    layers: HashMap<String, (u64, u64)>,
+}
+
+impl PullTracker {
+    fn percent(&mut self, line: &PullLine) -> Option<f64> {
+        if line.status == PULL_SUCCESS {
+            return Some(100.0);
+        }
+        if let (Some(digest), Some(total)) = (&line.digest, line.total) {
+            let completed = line.completed.unwrap_or(0).min(total);
+            self.layers.insert(digest.clone(), (completed, total));
+        }
+        let (completed, total) = self
+            .layers
+            .values()
+            .fold((0, 0), |(done, all), (completed, total)| {
+                (done + completed, all + total)
+            });
+        (total > 0).then(|| completed as f64 * 100.0 / total as f64)
+    }
+}
+
+/// Installed models, with the ones held in memory marked loaded.
+pub async fn list_models(
+    client: &ProviderClient,
+    http: &Client,
+) -> Result<Vec<ModelInfo>, LlmError> {
+    let installed: ModelList = client
+        .open(http.get(client.url("api/tags")).timeout(client.timeout()))
+        .await?
+        .json()
+        .await?;
+    // Older servers lack `/api/ps`; their models just show as available.
+    let loaded: HashSet<String> = match client
+        .open(http.get(client.url("api/ps")).timeout(client.timeout()))
+        .await
+    {
+        Ok(response) => response
+            .json::<ModelList>()
+            .await
+            .map(|running| running.models.into_iter().map(|model| model.name).collect())
+            .unwrap_or_default(),
+        Err(_) => HashSet::new(),
+    };
+
+    Ok(installed
+        .models
+        .into_iter()
+        .map(|model| {
+            let status = if loaded.contains(&model.name) {
+                ModelStatus::Loaded
+            } else {
+                ModelStatus::Available
+            };
+            model.info(status)
+        })
+        .collect())
+}
+
+pub async fn show_model(
+    client: &ProviderClient,
+    http: &Client,
+    name: &str,
+) -> Result<OllamaModelDetails, LlmError> {
+    let shown: ShowResponse = client
+        .open(
+            http.post(client.url("api/show"))
+                .timeout(client.timeout())
+                .json(&json!({ "model": name })),
+        )
+        .await?
+        .json()
+        .await?;
+
+    let context_length = shown
+        .model_info
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, value)| value.as_u64());
+    let has = |capability: &str| shown.capabilities.iter().any(|c| c == capability);
+    let model_type = if has("embedding") {
+        ModelType::Embedding
+    } else if has("vision") {
+        ModelType::Vision
+    } else {
+        ModelType::Text
+    };
+    let model = OllamaModel {
+        name: name.to_string(),
+        size: None,
+        digest: None,
+        modified_at: shown.modified_at,
+        expires_at: None,
+        details: shown.details,
+    };
+    let info = ModelInfo {
+        model_type: Some(model_type),
+        context_length,
+        ..model.info(ModelStatus::Available)
+    };
+    Ok(OllamaModelDetails {
+        info,
+        license: shown.license,
+        modelfile: shown.modelfile,
+        parameters: shown.parameters,
+        template: shown.template,
+        capabilities: shown.capabilities,
+        model_info: shown.model_info,
+    })
+}
+
+pub async fn delete_model(
+    client: &ProviderClient,
+    http: &Client,
+    name: &str,
+) -> Result<(), LlmError> {
+    client
+        .open(
+            http.delete(client.url("api/delete"))
+                .timeout(client.timeout())
+                .json(&json!({ "model": name })),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Downloads `name`, passing progress to `on_progress` as Ollama reports it.
+/// Only connecting is bounded by the provider timeout: verifying a large
+/// model can keep the server silent for a long time.
+pub async fn pull_model(
+    client: &ProviderClient,
+    http: &Client,
+    name: &str,
+    request_id: &str,
+    on_progress: impl Fn(&PullProgress) -> Result<(), LlmError>,
+) -> Result<PullProgress, LlmError> {
+    let builder = http
+        .post(client.url("api/pull"))
+        .json(&json!({ "model": name, "stream": true }));
+    let mut response = client.idle(client.open(builder)).await?;
+    let status = response.status().as_u16();
+
+    let mut tracker = PullTracker::default();
+    let mut lines = LineBuffer::default();
+    let mut last = None;
+    let mut handle = |line: &str| -> Result<(), LlmError> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let line: PullLine = serde_json::from_str(line).map_err(|e| LlmError::InvalidResponse {
+            message: e.to_string(),
+        })?;
+        if let Some(message) = line.error {
+            return Err(LlmError::Provider { status, message });
+        }
+        let progress = PullProgress {
+            request_id: request_id.to_string(),
+            model: name.to_string(),
+            percent: tracker.percent(&line),
+            status: line.status,
+            digest: line.digest,
+            total: line.total,
+            completed: line.completed,
+        };
+        on_progress(&progress)?;
+        last = Some(progress);
+        Ok(())
+    };
+    while let Some(chunk) = response.chunk().await? {
+        for line in lines.push(&chunk) {
+            handle(&line)?;
+        }
+    }
+    if let Some(line) = lines.finish() {
+        handle(&line)?;
+    }
+
+    last.filter(|progress| progress.status == PULL_SUCCESS)
+        .ok_or_else(|| LlmError::InvalidResponse {
+            message: format!("pull of {name} ended before it completed"),
+        })
+}
+
+fn ollama_client(state: &AppStateType) -> Result<ProviderClient, LlmError> {
+    let (_, client) = llm::provider_client(state, Some(LlmProvider::Ollama))?;
+    Ok(client)
+}
+
+#[tauri::command]
+pub async fn list_ollama_models(
+    state: State<'_, AppStateType>,
+    service: State<'_, LlmService>,
+) -> Result<Vec<ModelInfo>, LlmError> {
+    let client = ollama_client(&state)?;
+    list_models(&client, service.http()).await
+}
+
+#[tauri::command]
+pub async fn show_ollama_model(
+    name: String,
+    state: State<'_, AppStateType>,
+    service: State<'_, LlmService>,
+) -> Result<OllamaModelDetails, LlmError> {
+    let client = ollama_client(&state)?;
+    show_model(&client, service.http(), &name).await
+}
+
+#[tauri::command]
+pub async fn delete_ollama_model(
+    name: String,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+    service: State<'_, LlmService>,
+) -> Result<(), LlmError> {
+    let client = ollama_client(&state)?;
+    delete_model(&client, service.http(), &name).await?;
+    model_catalog::refresh(&app, vec![LlmProvider::Ollama]).await;
+    Ok(())
+}
+
+/// Pulls `name` onto the configured Ollama server, emitting `PullProgress`
+/// on `PULL_CHANNEL`, and then refreshes the Ollama models in the catalog.
+/// `cancel_request(request_id)` stops the download.
+#[tauri::command]
+pub async fn pull_ollama_model(
+    name: String,
+    request_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+    registry: State<'_, RequestRegistry>,
+) -> Result<PullProgress, LlmError> {
+    let client = ollama_client(&state)?;
+    let request_id = request_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let id = request_id.clone();
+    let emitter = app.clone();
+    let work = async move {
+        let service = emitter.state::<LlmService>();
+        pull_model(&client, service.http(), &name, &id, |progress| {
+            emitter
+                .emit(PULL_CHANNEL, progress)
+                .map_err(|e| LlmError::from(e.to_string()))
+        })
+        .await
+    };
+    let pulled = match registry
+        .run(&request_id, RequestOwner::default(), work)
+        .await?
+    {
+        Some(result) => result?,
+        None => return Err(LlmError::Cancelled { request_id }),
+    };
+    model_catalog::refresh(&app, vec![LlmProvider::Ollama]).await;
+    Ok(pulled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{client, run, stub, stub_stream};
+    use std::sync::Mutex;
+
+    /// Pulls `m1` from a stub sending `pieces`, returning the outcome and the
+    /// percentage of every progress report.
+    fn pull(pieces: Vec<&'static str>) -> (Result<PullProgress, LlmError>, Vec<Option<f64>>) {
+        let client = client(LlmProvider::Ollama, &stub_stream(pieces), None);
+        let reports = Mutex::new(Vec::new());
+        let result = run(pull_model(
+            &client,
+            &Client::new(),
+            "m1",
+            "pull-1",
+            |progress| {
+                reports.lock().unwrap().push(progress.percent);
+                Ok(())
+            },
+        ));
+        (result, reports.into_inner().unwrap())
+    }
+
+    #[test]
+    fn pull_progress_covers_every_layer() {
+        let (result, reports) = pull(vec![
+            "{\"status\":\"pulling manifest\"}\n",
+            "{\"status\":\"pulling a\",\"digest\":\"a\",\"total\":100,\"completed\":50}\n{\"status\":\"pulling b\",\"digest\":\"b\",\"total\":300}\n",
+            "{\"status\":\"pulling a\",\"digest\":\"a\",\"total\":100,\"completed\":100}\n{\"status\":\"pulling b\",\"digest\":\"b\",\"total\":300,\"completed\":300}\n",
+            "{\"status\":\"verifying sha256 digest\"}\n{\"status\":\"success\"}",
+        ]);
+        assert_eq!(
+            reports,
+            [
+                None,
+                Some(50.0),
+                Some(12.5),
+                Some(25.0),
+                Some(100.0),
+                Some(100.0),
+                Some(100.0)
+            ]
+        );
+        let last = result.unwrap();
+        assert_eq!(
+            (last.status.as_str(), last.request_id.as_str()),
+            ("success", "pull-1")
+        );
+    }
+
+    #[test]
+    fn pull_fails_on_errors_and_early_ends() {
+        let (result, reports) = pull(vec![
+            "{\"status\":\"pulling manifest\"}\n{\"error\":\"pull model manifest: file does not exist\"}\n",
+        ]);
+        assert_eq!(reports.len(), 1);
+        assert!(
+            matches!(result, Err(LlmError::Provider { status: 200, ref message }) if message == "pull model manifest: file does not exist"),
+            "{result:?}"
+        );
+
+        let (result, _) = pull(vec!["{\"status\":\"pulling manifest\"}\n"]);
+        assert!(
+            matches!(result, Err(LlmError::InvalidResponse { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn installed_models_are_listed_shown_and_deleted() {
+        let http = Client::new();
+        let (base_url, requests) = stub(vec![
+            (
+                200,
+                r#"{"models":[{"name":"qwen3:latest","size":5,"digest":"d1",
+                    "details":{"family":"qwen3","parameter_size":"8B","quantization_level":"Q4_K_M"}},
+                    {"name":"llama3","size":7}]}"#,
+            ),
+            (200, r#"{"models":[{"name":"qwen3:latest"}]}"#),
+            (
+                200,
+                r#"{"details":{"family":"llava"},"model_info":{"llama.context_length":8192},
+                    "capabilities":["completion","vision"]}"#,
+            ),
+            (200, ""),
+            (404, r#"{"error":"model 'x' not found"}"#),
+        ]);
+        let client = client(LlmProvider::Ollama, &base_url, None);
+
+        let models = run(list_models(&client, &http)).unwrap();
+        assert!(requests.recv().unwrap().starts_with("GET /api/tags "));
+        assert!(requests.recv().unwrap().starts_with("GET /api/ps "));
+        let statuses: Vec<_> = models
+            .iter()
+            .map(|model| (model.id.as_str(), model.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("qwen3:latest", Some(ModelStatus::Loaded)),
+                ("llama3", Some(ModelStatus::Available))
+            ]
+        );
+        assert_eq!(models[0].parameter_size.as_deref(), Some("8B"));
+
+        let details = run(show_model(&client, &http, "llava")).unwrap();
+        assert!(requests.recv().unwrap().ends_with(r#"{"model":"llava"}"#));
+        assert_eq!(details.info.context_length, Some(8192));
+        assert_eq!(details.info.model_type, Some(ModelType::Vision));
+
+        run(delete_model(&client, &http, "x")).unwrap();
+        assert!(requests.recv().unwrap().starts_with("DELETE /api/delete "));
+        let error = run(delete_model(&client, &http, "x")).unwrap_err();
+        assert!(
+            matches!(error, LlmError::Provider { status: 404, ref message } if message == "model 'x' not found"),
+            "{error}"
+        );
+    }
+}
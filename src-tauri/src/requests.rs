@@ -0,0 +1,200 @@
+use crate::events::{ESAFEvent, EventBus, EventType};
+use crate::AppStateType;
+use serde::Deserialize;
+use serde_json::{json, Map};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, State};
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+/// Reason recorded on the `task_failed` event of a cancelled request.
+const CANCELLED_REASON: &str = "cancelled";
+
+/// Source of cancellation events for requests not made on behalf of an
+/// agent.
+const BACKEND_SOURCE: &str = "backend";
+
+/// The task and agent a backend request works for, if any.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOwner {
+    #[serde(default)]
+    pub task_id: Option<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+}
+
+struct RunningRequest {
+    abort: AbortHandle,
+    owner: RequestOwner,
+    /// Tells this run apart from a later one reusing its id.
+    run: Uuid,
+}
+
+/// Long-running backend work, such as LLM requests, that the frontend can
+/// cancel by id.
+#[derive(Default)]
+pub struct RequestRegistry {
+    running: Arc<Mutex<HashMap<String, RunningRequest>>>,
+}
+
+impl RequestRegistry {
+    /// Runs `work` on its own task under `id` until it finishes or
+    /// `cancel` aborts it, dropping whatever connection it holds. Returns
+    /// `None` when cancelled, including when `cancel` won the race with
+    /// `work` finishing, so a cancelled request never also succeeds.
+    pub async fn run<T, F>(
+        &self,
+        id: &str,
+        owner: RequestOwner,
+        work: F,
+    ) -> Result<Option<T>, String>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let run = Uuid::new_v4();
+        let handle = {
+            let mut running = self.running.lock().map_err(|e| e.to_string())?;
+            if running.contains_key(id) {
+                return Err(format!("request {id} is already running"));
+            }
+            let registry = Arc::clone(&self.running);
+            let key = id.to_string();
+            // The finished work unregisters itself under the same lock
+            // `cancel` takes, so exactly one of them claims the request.
+            let handle = tauri::async_runtime::spawn(async move {
+                let value = work.await;
+                let mut running = registry.lock().map_err(|e| e.to_string())?;
+                Ok(Self::claim(&mut running, &key, run).map(|_| value))
+            });
+            let abort = handle.inner().abort_handle();
+            running.insert(id.to_string(), RunningRequest { abort, owner, run });
+            handle
+        };
+
+        match handle.await {
+            Ok(result) => result,
+            Err(tauri::Error::JoinError(e)) if e.is_cancelled() => Ok(None),
+            Err(e) => {
+                if let Ok(mut running) = self.running.lock() {
+                    Self::claim(&mut running, id, run);
+                }
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Aborts the request running under `id`, returning its owner, or `None`
+    /// if it already finished.
+    pub fn cancel(&self, id: &str) -> Result<Option<RequestOwner>, String> {
+        let mut running = self.running.lock().map_err(|e| e.to_string())?;
+        Ok(running.remove(id).map(|request| {
+            request.abort.abort();
+            request.owner
+        }))
+    }
+
+    /// Unregisters `run` of `id`, unless it was cancelled and its id is free
+    /// or taken by a later run.
+    fn claim(
+        running: &mut HashMap<String, RunningRequest>,
+        id: &str,
+        run: Uuid,
+    ) -> Option<RunningRequest> {
+        if running.get(id)?.run != run {
+            return None;
+        }
+        running.remove(id)
+    }
+}
+
+/// Aborts the backend request `request_id` and, if it worked on a task,
+/// publishes a `task_failed` event with reason `cancelled` for that task.
+/// Returns false if the request had already finished.
+#[tauri::command]
+pub fn cancel_request(
+    request_id: String,
+    app: AppHandle,
+    registry: State<RequestRegistry>,
+    bus: State<EventBus>,
+    state: State<AppStateType>,
+) -> Result<bool, String> {
+    let Some(owner) = registry.cancel(&request_id)? else {
+        return Ok(false);
+    };
+    let Some(task_id) = owner.task_id else {
+        return Ok(true);
+    };
+
+    let mut payload = Map::new();
+    payload.insert("reason".to_string(), json!(CANCELLED_REASON));
+    payload.insert("requestId".to_string(), json!(request_id));
+    payload.insert(
+        "error".to_string(),
+        json!(format!("request {request_id} was cancelled")),
+    );
+    let source = owner.agent_id.as_deref().unwrap_or(BACKEND_SOURCE);
+    let event = ESAFEvent::new(EventType::TaskFailed, source, payload, Some(task_id));
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    bus.publish(&app, &mut app_state, event)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::run;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Cancels `id` on `registry` from another thread after `delay`.
+    fn cancel_after(
+        registry: &Arc<RequestRegistry>,
+        id: &'static str,
+        delay: Duration,
+    ) -> thread::JoinHandle<Option<RequestOwner>> {
+        let registry = Arc::clone(registry);
+        thread::spawn(move || {
+            thread::sleep(delay);
+            registry.cancel(id).unwrap()
+        })
+    }
+
+    #[test]
+    fn cancel_aborts_waiting_work() {
+        let registry = Arc::new(RequestRegistry::default());
+        let owner = RequestOwner {
+            task_id: Some("t1".to_string()),
+            agent_id: None,
+        };
+        let canceller = cancel_after(&registry, "r1", Duration::from_millis(50));
+        let result = run(registry.run("r1", owner, async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }));
+        assert_eq!(result, Ok(None));
+        let owner = canceller.join().unwrap().unwrap();
+        assert_eq!(owner.task_id.as_deref(), Some("t1"));
+
+        assert!(registry.cancel("r1").unwrap().is_none());
+        let result = run(registry.run("r1", RequestOwner::default(), async { 5 }));
+        assert_eq!(result, Ok(Some(5)));
+        assert!(registry.cancel("r1").unwrap().is_none());
+    }
+
+    #[test]
+    fn work_finishing_after_a_cancel_is_not_returned() {
+        let registry = Arc::new(RequestRegistry::default());
+        let canceller = cancel_after(&registry, "r1", Duration::from_millis(50));
+        // Blocking work cannot be aborted, so it finishes after `cancel`
+        // has already reported the request as cancelled.
+        let result = run(registry.run("r1", RequestOwner::default(), async {
+            thread::sleep(Duration::from_millis(300));
+            5
+        }));
+        assert!(canceller.join().unwrap().is_some());
+        assert_eq!(result, Ok(None));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::journal::Mutation;
+use crate::store::{json_column, to_json, Store};
+use crate::AppStateType;
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::State;
+
+/// Analysis result with confidence metrics, mirroring the frontend
+/// `AnalysisResultSchema`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisResult {
+    pub id: String,
+    pub source_task_id: String,
+    pub agent_id: String,
+    pub result: Map<String, Value>,
+    pub confidence: f64,
+    pub timestamp: i64,
+    pub metadata: Map<String, Value>,
+}
+
+impl AnalysisResult {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("analysis result id must not be empty".to_string());
+        }
+        if self.source_task_id.trim().is_empty() {
+            return Err(format!("analysis result {} has no sourceTaskId", self.id));
+        }
+        if self.agent_id.trim().is_empty() {
+            return Err(format!("analysis result {} has no agentId", self.id));
+        }
+        if !(0.0..=1.0).contains(&self.confidence) {
+            return Err(format!(
+                "analysis result {} has confidence {} outside [0, 1]",
+                self.id, self.confidence
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(AnalysisResult {
+            id: row.get("id")?,
+            source_task_id: row.get("source_task_id")?,
+            agent_id: row.get("agent_id")?,
+            result: json_column(row, "result")?,
+            confidence: row.get("confidence")?,
+            timestamp: row.get("timestamp")?,
+            metadata: json_column(row, "metadata")?,
+        })
+    }
+}
+
+impl Store {
+    pub fn insert_result(&self, result: &AnalysisResult) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO results
+                (id, source_task_id, agent_id, result, confidence, timestamp, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET
+                source_task_id = excluded.source_task_id,
+                agent_id = excluded.agent_id,
+                result = excluded.result,
+                confidence = excluded.confidence,
+                timestamp = excluded.timestamp,
+                metadata = excluded.metadata",
+            params![
+                result.id,
+                result.source_task_id,
+                result.agent_id,
+                to_json(&result.result)?,
+                result.confidence,
+                result.timestamp,
+                to_json(&result.metadata)?,
+            ],
+        )?;
+        self.index_result(result)
+    }
+
+    /// Stored results, oldest first, optionally limited to one source task.
+    pub fn results(&self, task_id: Option<&str>) -> rusqlite::Result<Vec<AnalysisResult>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM results
+             WHERE ?1 IS NULL OR source_task_id = ?1
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(params![task_id], AnalysisResult::from_row)?;
+        rows.collect()
+    }
+}
+
+#[tauri::command]
+pub fn save_analysis_result(
+    result: AnalysisResult,
+    state: State<AppStateType>,
+) -> Result<(), String> {
+    result.validate()?;
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.commit(Mutation::InsertResult { result })
+}
+
+#[tauri::command]
+pub fn get_analysis_results(
+    task_id: Option<String>,
+    state: State<AppStateType>,
+) -> Result<Vec<AnalysisResult>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state
+        .store
+        .results(task_id.as_deref())
+        .map_err(|e| e.to_string())
+}
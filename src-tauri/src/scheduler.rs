@@ -0,0 +1,289 @@
+use crate::lifecycle;
+use crate::store::now_millis;
+use crate::task_graph::TaskGraph;
+use crate::tasks::{Task, TaskStatus};
+use crate::{AppState, AppStateType};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use tauri::State;
+
+/// Time a pending task has to wait to gain one priority level, so that LOW
+/// tasks eventually outrank a steady stream of newer CRITICAL ones, unless
+/// the `scheduler.agingIntervalMs` setting says otherwise.
+pub const DEFAULT_AGING_INTERVAL_MS: i64 = 5 * 60 * 1000;
+
+/// Keyword routing from task types to agent types, mirroring
+/// `ESAFOrchestrator.assignTask`.
+const ROUTES: &[(&str, &[&str])] = &[
+    (
+        "DataAnalysis",
+        &[
+            "data",
+            "validation",
+            "feature",
+            "intelligent",
+            "anomaly",
+            "backup",
+        ],
+    ),
+    (
+        "OptimizationAgent",
+        &[
+            "optimization",
+            "constraint",
+            "algorithm_selection",
+            "solve",
+            "multi_objective",
+            "relaxation",
+        ],
+    ),
+    (
+        "GameTheoryAgent",
+        &[
+            "strategy",
+            "equilibrium",
+            "conflict",
+            "coalition",
+            "game",
+            "mechanism",
+        ],
+    ),
+    (
+        "SwarmIntelligenceAgent",
+        &[
+            "adaptive",
+            "swarm",
+            "learning",
+            "emergent",
+            "memory",
+            "system_adaptation",
+        ],
+    ),
+    (
+        "DecisionMakingAgent",
+        &[
+            "decision",
+            "integration",
+            "criteria",
+            "contingency",
+            "fallback",
+            "stakeholder",
+            "final_recommendation",
+        ],
+    ),
+];
+
+/// Returns the agent type responsible for `task_type`, if any.
+pub fn agent_type_for_task(task_type: &str) -> Option<&'static str> {
+    ROUTES
+        .iter()
+        .find(|(_, keywords)| keywords.iter().any(|keyword| task_type.contains(keyword)))
+        .map(|(agent_type, _)| *agent_type)
+}
+
+/// Picks runnable tasks by aged priority, then age, then id.
+pub struct Scheduler {
+    aging_interval_ms: i64,
+}
+
+impl Scheduler {
+    pub fn new(aging_interval_ms: i64) -> Self {
+        Scheduler { aging_interval_ms }
+    }
+
+    /// Priority of `task` after adding one level per aging interval waited.
+    pub fn effective_priority(&self, task: &Task, now: i64) -> f64 {
+        let waited = (now - task.created_at).max(0) as f64;
+        f64::from(u8::from(task.priority)) + waited / self.aging_interval_ms as f64
+    }
+
+    /// Dependency-satisfied pending tasks routed to `agent_type` (or all of
+    /// them when `None`), in the order they would be claimed.
+    pub fn queue<'a>(
+        &self,
+        tasks: &'a HashMap<String, Task>,
+        agent_type: Option<&str>,
+        now: i64,
+    ) -> Vec<&'a Task> {
+        let mut queue: Vec<&Task> = TaskGraph::new(tasks)
+            .ready()
+            .into_iter()
+            .filter(|task| {
+                agent_type.is_none_or(|agent_type| {
+                    agent_type_for_task(&task.task_type) == Some(agent_type)
+                })
+            })
+            .collect();
+
+        queue.sort_by(|a, b| {
+            self.effective_priority(b, now)
+                .partial_cmp(&self.effective_priority(a, now))
+                .unwrap_or(Ordering::Equal)
+                .then(a.created_at.cmp(&b.created_at))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        queue
+    }
+}
+
+/// Scheduler configured by the `scheduler.*` settings.
+fn configured_scheduler(state: &AppState) -> Result<Scheduler, String> {
+    let settings = state.settings().map_err(|e| e.to_string())?.settings;
+    Ok(Scheduler::new(settings.scheduler.aging_interval_ms))
+}
+
+#[tauri::command]
+pub fn get_task_queue(
+    agent_type: Option<String>,
+    state: State<AppStateType>,
+) -> Result<Vec<Task>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let tasks = app_state.store.tasks().map_err(|e| e.to_string())?;
+    let scheduler = configured_scheduler(&app_state)?;
+    let queue = scheduler.queue(&tasks, agent_type.as_deref(), now_millis());
+    Ok(queue.into_iter().cloned().collect())
+}
+
+/// Assigns the next runnable task for `agent_type` at `now` to `agent_id`.
+/// Taking the state mutably keeps selection and write together, so two
+/// agents never claim the same task.
+fn claim(
+    state: &mut AppState,
+    agent_type: &str,
+    agent_id: String,
+    now: i64,
+) -> Result<Option<Task>, String> {
+    let tasks = state.store.tasks().map_err(|e| e.to_string())?;
+    let scheduler = configured_scheduler(state)?;
+    let Some(next) = scheduler
+        .queue(&tasks, Some(agent_type), now)
+        .first()
+        .copied()
+    else {
+        return Ok(None);
+    };
+
+    let claimed = lifecycle::transition_stored_task(
+        state,
+        &next.id,
+        TaskStatus::Assigned,
+        Some(agent_id),
+        Some("claimed by scheduler".to_string()),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(Some(claimed))
+}
+
+/// Assigns the next runnable task for `agent_type` to `agent_id`. The state
+/// lock is held from selection to write.
+#[tauri::command]
+pub fn claim_next_task(
+    agent_type: String,
+    agent_id: String,
+    state: State<AppStateType>,
+) -> Result<Option<Task>, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    claim(&mut app_state, &agent_type, agent_id, now_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::TaskPriority;
+    use crate::test_support::{add_task, app_state, task, temp_dir};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn prioritized(id: &str, priority: TaskPriority, created_at: i64) -> Task {
+        Task {
+            priority,
+            created_at,
+            ..task(id, &[])
+        }
+    }
+
+    #[test]
+    fn queue_orders_by_aged_priority_then_age() {
+        let tasks: HashMap<_, _> = [
+            prioritized("new-high", TaskPriority::High, 1000),
+            prioritized("old-high", TaskPriority::High, 500),
+            prioritized("medium", TaskPriority::Medium, 900),
+            Task {
+                task_type: "swarm_learning".to_string(),
+                ..prioritized("other-agent", TaskPriority::Critical, 1000)
+            },
+        ]
+        .into_iter()
+        .map(|task| (task.id.clone(), task))
+        .collect();
+        let ids = |queue: Vec<&Task>| -> Vec<String> {
+            queue.into_iter().map(|task| task.id.clone()).collect()
+        };
+
+        let scheduler = Scheduler::new(DEFAULT_AGING_INTERVAL_MS);
+        let queue = scheduler.queue(&tasks, Some("DataAnalysis"), 1000);
+        assert_eq!(ids(queue), ["old-high", "new-high", "medium"]);
+
+        // Waiting 200 ms is worth two levels at 100 ms per level.
+        let scheduler = Scheduler::new(100);
+        let queue = scheduler.queue(&tasks, Some("DataAnalysis"), 1000);
+        assert_eq!(ids(queue), ["old-high", "medium", "new-high"]);
+    }
+
+    #[test]
+    fn aging_lets_an_old_low_task_overtake_a_new_critical_one() {
+        let scheduler = Scheduler::new(DEFAULT_AGING_INTERVAL_MS);
+        // A CRITICAL task is three levels above a LOW one, so the LOW task
+        // draws level after three intervals of waiting and then wins on age.
+        let first_at = |now: i64| {
+            let tasks: HashMap<_, _> = [
+                prioritized("old-low", TaskPriority::Low, 0),
+                prioritized("new-critical", TaskPriority::Critical, now),
+            ]
+            .into_iter()
+            .map(|task| (task.id.clone(), task))
+            .collect();
+            scheduler.queue(&tasks, None, now)[0].id.clone()
+        };
+
+        assert_eq!(first_at(2 * DEFAULT_AGING_INTERVAL_MS), "new-critical");
+        assert_eq!(first_at(3 * DEFAULT_AGING_INTERVAL_MS), "old-low");
+    }
+
+    #[test]
+    fn concurrent_claims_never_take_the_same_task() {
+        let root = temp_dir("scheduler");
+        let mut state = app_state(&root);
+        add_task(&mut state, prioritized("first", TaskPriority::High, 0));
+        add_task(&mut state, prioritized("second", TaskPriority::Medium, 0));
+        let state = Arc::new(Mutex::new(state));
+
+        let claimers: Vec<_> = ["agent-1", "agent-2", "agent-3"]
+            .into_iter()
+            .map(|agent_id| {
+                let state = Arc::clone(&state);
+                thread::spawn(move || {
+                    let mut state = state.lock().unwrap();
+                    claim(&mut state, "DataAnalysis", agent_id.to_string(), 1000).unwrap()
+                })
+            })
+            .collect();
+        let mut claimed: Vec<_> = claimers
+            .into_iter()
+            .filter_map(|claimer| claimer.join().unwrap())
+            .collect();
+        claimed.sort_by(|a, b| a.id.cmp(&b.id));
+
+        assert_eq!(claimed.len(), 2);
+        assert_eq!(
+            (claimed[0].id.as_str(), claimed[1].id.as_str()),
+            ("first", "second")
+        );
+        assert_ne!(claimed[0].assigned_agent_id, claimed[1].assigned_agent_id);
+        assert!(claimed
+            .iter()
+            .all(|task| task.status == TaskStatus::Assigned));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,640 @@
+use crate::chats::ChatMessage;
+use crate::documents::DocumentEntry;
+use crate::results::AnalysisResult;
+use crate::store::{json_column, to_json, Store};
+use crate::AppStateType;
+use rusqlite::types::{
+    FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Value as SqlValue, ValueRef,
+};
+use rusqlite::{params, params_from_iter, Row, ToSql};
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use tauri::State;
+
+/// Number of hits returned when a query does not specify a limit.
+pub const DEFAULT_SEARCH_LIMIT: u32 = 20;
+
+/// Largest number of hits a single query may request.
+pub const MAX_SEARCH_LIMIT: u32 = 100;
+
+/// BM25 term frequency saturation.
+const K1: f64 = 1.2;
+
+/// BM25 document length normalization.
+const B: f64 = 0.75;
+
+/// Characters of context kept on either side of the first match in a
+/// snippet.
+const SNIPPET_RADIUS: usize = 80;
+
+/// Kind of content a search hit comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    Document,
+    ChatMessage,
+    AnalysisResult,
+}
+
+impl SearchKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SearchKind::Document => "document",
+            SearchKind::ChatMessage => "chat_message",
+            SearchKind::AnalysisResult => "analysis_result",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "document" => Some(SearchKind::Document),
+            "chat_message" => Some(SearchKind::ChatMessage),
+            "analysis_result" => Some(SearchKind::AnalysisResult),
+            _ => None,
+        }
+    }
+
+    fn key(self, source_id: &str) -> String {
+        format!("{}:{source_id}", self.as_str())
+    }
+}
+
+impl ToSql for SearchKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for SearchKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        SearchKind::parse(raw)
+            .ok_or_else(|| FromSqlError::Other(format!("invalid search kind: {raw}").into()))
+    }
+}
+
+/// Filters for `search`. `since`/`until` bound the timestamp inclusively.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchQuery {
+    /// Words to look for; text in double quotes must appear as a phrase.
+    pub query: String,
+    pub kinds: Vec<SearchKind>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub id: String,
+    /// Chat session of a message, or source task of an analysis result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub timestamp: i64,
+    pub score: f64,
+    /// HTML-escaped excerpt with matches wrapped in `<mark>`.
+    pub snippet: String,
+}
+
+/// Text of one searchable item.
+struct IndexEntry<'a> {
+    kind: SearchKind,
+    source_id: &'a str,
+    parent_id: Option<&'a str>,
+    title: String,
+    timestamp: i64,
+    body: String,
+}
+
+struct Token {
+    term: String,
+    start: usize,
+    end: usize,
+}
+
+/// Splits `text` into lowercased, stemmed words with their byte ranges.
+fn tokenize(text: &str) -> Vec<Token> {
+    let stemmer = Stemmer::create(Algorithm::English);
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                let word = text[begin..index].to_lowercase();
+                tokens.push(Token {
+                    term: stemmer.stem(&word).into_owned(),
+                    start: begin,
+                    end: index,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// A parsed query: every term to score, and the phrases that must match.
+struct ParsedQuery {
+    terms: Vec<String>,
+    phrases: Vec<Vec<String>>,
+}
+
+impl ParsedQuery {
+    fn parse(query: &str) -> Self {
+        let mut terms = Vec::new();
+        let mut phrases = Vec::new();
+        for (index, part) in query.split('"').enumerate() {
+            let words: Vec<String> = tokenize(part).into_iter().map(|token| token.term).collect();
+            // Odd parts sit between a pair of quotes.
+            if index % 2 == 1 && !words.is_empty() {
+                phrases.push(words.clone());
+            }
+            for word in words {
+                if !terms.contains(&word) {
+                    terms.push(word);
+                }
+            }
+        }
+        ParsedQuery { terms, phrases }
+    }
+
+    /// Whether every phrase occurs in a document with the given term
+    /// positions.
+    fn phrases_match(&self, positions: &HashMap<String, Vec<u32>>) -> bool {
+        self.phrases.iter().all(|phrase| {
+            let Some(first) = positions.get(&phrase[0]) else {
+                return false;
+            };
+            first.iter().any(|&start| {
+                phrase.iter().enumerate().skip(1).all(|(offset, term)| {
+                    positions
+                        .get(term)
+                        .is_some_and(|found| found.contains(&(start + offset as u32)))
+                })
+            })
+        })
+    }
+}
+
+/// Flattens the strings and numbers of a JSON value into searchable text.
+fn json_text(value: &Value, text: &mut String) {
+    match value {
+        Value::String(s) => {
+            text.push_str(s);
+            text.push('\n');
+        }
+        Value::Number(n) => {
+            text.push_str(&n.to_string());
+            text.push('\n');
+        }
+        Value::Array(items) => items.iter().for_each(|item| json_text(item, text)),
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                text.push_str(key);
+                text.push('\n');
+                json_text(field, text);
+            }
+        }
+        Value::Bool(_) | Value::Null => {}
+    }
+}
+
+pub(crate) fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Excerpt of `body` around the first query term, with every query term in
+/// it highlighted.
+fn snippet(body: &str, terms: &HashSet<&str>) -> String {
+    let tokens = tokenize(body);
+    let center = tokens
+        .iter()
+        .find(|token| terms.contains(token.term.as_str()))
+        .map_or(0, |token| token.start);
+
+    let start = body[..center]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_RADIUS - 1)
+        .map_or(0, |(index, _)| index);
+    let end = body[center..]
+        .char_indices()
+        .nth(SNIPPET_RADIUS)
+        .map_or(body.len(), |(index, _)| center + index);
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut cursor = start;
+    for token in tokens
+        .iter()
+        .filter(|token| token.start >= start && token.end <= end)
+        .filter(|token| terms.contains(token.term.as_str()))
+    {
+        escape_html(&body[cursor..token.start], &mut out);
+        out.push_str("<mark>");
+        escape_html(&body[token.start..token.end], &mut out);
+        out.push_str("</mark>");
+        cursor = token.end;
+    }
+    escape_html(&body[cursor..end], &mut out);
+    if end < body.len() {
+        out.push('…');
+    }
+    out
+}
+
+impl Store {
+    /// Replaces the index entry of one item. Title and body are both
+    /// searchable; snippets come from the body. Document bodies are not
+    /// copied into the index, since the blob store already holds them.
+    fn index_entry(&self, entry: &IndexEntry) -> rusqlite::Result<()> {
+        let key = entry.kind.key(entry.source_id);
+        self.conn
+            .execute("DELETE FROM search_docs WHERE key = ?1", params![key])?;
+
+        let tokens = tokenize(&format!("{}\n{}", entry.title, entry.body));
+        let body = match entry.kind {
+            SearchKind::Document => "",
+            _ => entry.body.as_str(),
+        };
+        self.conn.execute(
+            "INSERT INTO search_docs
+                (key, kind, source_id, parent_id, title, timestamp, length, body)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                key,
+                entry.kind,
+                entry.source_id,
+                entry.parent_id,
+                entry.title,
+                entry.timestamp,
+                tokens.len() as i64,
+                body,
+            ],
+        )?;
+
+        let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+        for (position, token) in tokens.into_iter().enumerate() {
+            positions
+                .entry(token.term)
+                .or_default()
+                .push(position as u32);
+        }
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT INTO search_postings (term, key, tf, positions) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (term, positions) in positions {
+            stmt.execute(params![
+                term,
+                key,
+                positions.len() as i64,
+                to_json(&positions)?
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Indexes `document` with its body read from the blob store.
+    pub fn index_document(&self, document: &DocumentEntry) -> rusqlite::Result<()> {
+        let body = self
+            .blobs
+            .content(document)
+            .map_err(|message| rusqlite::Error::ToSqlConversionFailure(message.into()))?;
+        self.index_entry(&IndexEntry {
+            kind: SearchKind::Document,
+            source_id: &document.id,
+            parent_id: None,
+            title: document.name.clone(),
+            timestamp: document.metadata.created,
+            body,
+        })
+    }
+
+    pub fn index_chat_message(&self, message: &ChatMessage) -> rusqlite::Result<()> {
+        self.index_entry(&IndexEntry {
+            kind: SearchKind::ChatMessage,
+            source_id: &message.id,
+            parent_id: Some(&message.session_id),
+            title: message
+                .agent_name
+                .clone()
+                .unwrap_or_else(|| message.message_type.as_str().to_string()),
+            timestamp: message.timestamp,
+            body: message.content.clone(),
+        })
+    }
+
+    pub fn index_result(&self, result: &AnalysisResult) -> rusqlite::Result<()> {
+        let mut body = String::new();
+        json_text(&Value::Object(result.result.clone()), &mut body);
+        json_text(&Value::Object(result.metadata.clone()), &mut body);
+        self.index_entry(&IndexEntry {
+            kind: SearchKind::AnalysisResult,
+            source_id: &result.id,
+            parent_id: Some(&result.source_task_id),
+            title: format!(
+                "{} result for task {}",
+                result.agent_id, result.source_task_id
+            ),
+            timestamp: result.timestamp,
+            body,
+        })
+    }
+
+    pub fn unindex(&self, kind: SearchKind, source_id: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "DELETE FROM search_docs WHERE key = ?1",
+            params![kind.key(source_id)],
+        )?;
+        Ok(())
+    }
+
+    pub fn unindex_chat_session(&self, session_id: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "DELETE FROM search_docs WHERE kind = ?1 AND parent_id = ?2",
+            params![SearchKind::ChatMessage, session_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_search_index(&self) -> rusqlite::Result<()> {
+        self.conn.execute("DELETE FROM search_docs", [])?;
+        Ok(())
+    }
+
+    /// Indexes items stored before the search index existed, or whose index
+    /// entry was lost.
+    pub fn backfill_search_index(&self) -> Result<(), String> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| e.to_string())?;
+
+        let messages = self
+            .unindexed(
+                "chat_messages",
+                SearchKind::ChatMessage,
+                ChatMessage::from_row,
+            )
+            .map_err(|e| e.to_string())?;
+        for message in &messages {
+            self.index_chat_message(message)
+                .map_err(|e| e.to_string())?;
+        }
+
+        let results = self
+            .unindexed(
+                "results",
+                SearchKind::AnalysisResult,
+                AnalysisResult::from_row,
+            )
+            .map_err(|e| e.to_string())?;
+        for result in &results {
+            self.index_result(result).map_err(|e| e.to_string())?;
+        }
+
+        let documents = self
+            .unindexed("documents", SearchKind::Document, DocumentEntry::from_row)
+            .map_err(|e| e.to_string())?;
+        for entry in &documents {
+            self.index_document(entry).map_err(|e| e.to_string())?;
+        }
+
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    /// Body of the document `document_id` for its snippet. A document
+    /// removed since the index was read has none.
+    fn document_content(&self, document_id: &str) -> Result<String, String> {
+        match self.document(document_id).map_err(|e| e.to_string())? {
+            Some(entry) => self.blobs.content(&entry),
+            None => Ok(String::new()),
+        }
+    }
+
+    fn unindexed<T>(
+        &self,
+        table: &str,
+        kind: SearchKind,
+        from_row: fn(&Row) -> rusqlite::Result<T>,
+    ) -> rusqlite::Result<Vec<T>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT * FROM {table}
+             WHERE ?1 || ':' || id NOT IN (SELECT key FROM search_docs WHERE kind = ?1)"
+        ))?;
+        let rows = stmt.query_map(params![kind], from_row)?;
+        rows.collect()
+    }
+
+    /// Ranks indexed items against `query` with BM25. Items match when they
+    /// contain any query word and every quoted phrase.
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, String> {
+        if let (Some(since), Some(until)) = (query.since, query.until) {
+            if since > until {
+                return Err(format!("search range is empty: {since} > {until}"));
+            }
+        }
+        let parsed = ParsedQuery::parse(&query.query);
+        if parsed.terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (total, average_length): (i64, f64) = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(AVG(length), 0) FROM search_docs",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        let total = total as f64;
+        let average_length = average_length.max(1.0);
+
+        let mut clauses = vec!["p.term = ?".to_string()];
+        let mut filters: Vec<SqlValue> = Vec::new();
+        if !query.kinds.is_empty() {
+            clauses.push(format!(
+                "d.kind IN ({})",
+                vec!["?"; query.kinds.len()].join(", ")
+            ));
+            filters.extend(
+                query
+                    .kinds
+                    .iter()
+                    .map(|kind| SqlValue::Text(kind.as_str().to_string())),
+            );
+        }
+        if let Some(since) = query.since {
+            clauses.push("d.timestamp >= ?".to_string());
+            filters.push(SqlValue::Integer(since));
+        }
+        if let Some(until) = query.until {
+            clauses.push("d.timestamp <= ?".to_string());
+            filters.push(SqlValue::Integer(until));
+        }
+        let sql = format!(
+            "SELECT p.key, p.tf, p.positions, d.length
+             FROM search_postings p JOIN search_docs d ON d.key = p.key
+             WHERE {}",
+            clauses.join(" AND ")
+        );
+        let mut stmt = self.conn.prepare(&sql).map_err(|e| e.to_string())?;
+
+        let mut scores: HashMap<String, f64> = HashMap::new();
+        let mut positions: HashMap<String, HashMap<String, Vec<u32>>> = HashMap::new();
+        for term in &parsed.terms {
+            let document_frequency: i64 = self
+                .conn
+                .query_row(
+                    "SELECT COUNT(*) FROM search_postings WHERE term = ?1",
+                    params![term],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            let df = document_frequency as f64;
+            let idf = ((total - df + 0.5) / (df + 0.5) + 1.0).ln();
+
+            let values =
+                std::iter::once(SqlValue::Text(term.clone())).chain(filters.iter().cloned());
+            let postings = stmt
+                .query_map(params_from_iter(values), |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        json_column::<Vec<u32>>(row, "positions")?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                })
+                .map_err(|e| e.to_string())?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(|e| e.to_string())?;
+
+            for (key, tf, term_positions, length) in postings {
+                let tf = tf as f64;
+                let norm = K1 * (1.0 - B + B * length as f64 / average_length);
+                *scores.entry(key.clone()).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+                positions
+                    .entry(key)
+                    .or_default()
+                    .insert(term.clone(), term_positions);
+            }
+        }
+
+        let mut ranked: Vec<(String, f64)> = scores
+            .into_iter()
+            .filter(|(key, _)| parsed.phrases_match(&positions[key]))
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT);
+        ranked.truncate(limit as usize);
+
+        let terms: HashSet<&str> = parsed.terms.iter().map(String::as_str).collect();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM search_docs WHERE key = ?1")
+            .map_err(|e| e.to_string())?;
+        ranked
+            .into_iter()
+            .map(|(key, score)| {
+                let (mut hit, body) = stmt
+                    .query_row(params![key], |row| {
+                        let hit = SearchHit {
+                            kind: row.get("kind")?,
+                            id: row.get("source_id")?,
+                            parent_id: row.get("parent_id")?,
+                            title: row.get("title")?,
+                            timestamp: row.get("timestamp")?,
+                            score,
+                            snippet: String::new(),
+                        };
+                        Ok((hit, row.get::<_, String>("body")?))
+                    })
+                    .map_err(|e| e.to_string())?;
+                let body = match hit.kind {
+                    SearchKind::Document => self.document_content(&hit.id)?,
+                    _ => body,
+                };
+                hit.snippet = snippet(&body, &terms);
+                Ok(hit)
+            })
+            .collect()
+    }
+}
+
+#[tauri::command]
+pub fn search(query: SearchQuery, state: State<AppStateType>) -> Result<Vec<SearchHit>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.store.search(&query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::DocumentSource;
+    use crate::store::DATABASE_FILE;
+    use serde_json::json;
+    use std::fs;
+    use uuid::Uuid;
+
+    #[test]
+    fn document_snippets_come_from_the_blob_store() {
+        let dir = std::env::temp_dir().join(format!("esaf-search-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let store = Store::open(&dir.join(DATABASE_FILE)).unwrap();
+        let document: DocumentSource = serde_json::from_value(json!({
+            "id": "d1",
+            "name": "Notes",
+            "type": "text",
+            "content": "The quarterly forecast improved <a lot>.",
+            "metadata": { "created": 1, "lastAccessed": 1 },
+        }))
+        .unwrap();
+        store.blobs.put(document.content.as_bytes()).unwrap();
+        store.save_document(&document.entry()).unwrap();
+
+        let stored: String = store
+            .conn
+            .query_row("SELECT body FROM search_docs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, "");
+
+        let query = SearchQuery {
+            query: "forecasts".to_string(),
+            ..SearchQuery::default()
+        };
+        let hits = store.search(&query).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].snippet,
+            "The quarterly <mark>forecast</mark> improved &lt;a lot&gt;."
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
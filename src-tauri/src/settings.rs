@@ -0,0 +1,875 @@
+use crate::journal::Mutation;
+use crate::scheduler::DEFAULT_AGING_INTERVAL_MS;
+use crate::storage;
+use crate::store::{json_column, to_json, Store};
+use crate::tombstones::DEFAULT_TASK_RETENTION_MS;
+use crate::{AppState, AppStateType};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, State};
+
+/// Settings shared by all workspaces, in the app data directory. Read at
+/// startup, so hand edits take effect on the next start.
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Top-level settings that apply to the whole installation. They are saved
+/// to `SETTINGS_FILE` and never read from a workspace.
+const INSTALLATION_SETTINGS: &[&str] = &["storage"];
+
+/// Tauri event emitted with the new [`ResolvedSettings`] whenever the
+/// effective settings may have changed.
+pub const SETTINGS_CHANNEL: &str = "esaf-settings";
+
+/// Prefix of the environment variables that override settings.
+pub const ENV_PREFIX: &str = "ESAF_";
+
+/// Environment variable suffixes and the settings they override, besides the
+/// per-provider ones built from [`PROVIDER_ENV`] and [`PROVIDER_FIELD_ENV`].
+const ENV_FIELDS: &[(&str, &str)] = &[
+    ("THEME", "theme"),
+    ("TASK_RETENTION_MS", "taskRetentionMs"),
+    ("DEFAULT_LLM_PROVIDER", "llm.defaultProvider"),
+    ("MAX_CONCURRENT_LLM_REQUESTS", "llm.maxConcurrentRequests"),
+    ("LLM_REQUEST_TIMEOUT", "llm.requestTimeoutMs"),
+    ("ENABLE_LLM_CACHING", "llm.enableCaching"),
+    ("LLM_CACHE_DURATION", "llm.cacheDurationMs"),
+    ("LLM_DEBUG_LOGGING", "llm.debugLogging"),
+    ("MOCK_LLM_RESPONSES", "llm.mockResponses"),
+    ("MOCK_RESPONSE_DELAY", "llm.mockResponseDelayMs"),
+    ("STORAGE_SOFT_QUOTA", "storage.softQuotaBytes"),
+    ("STORAGE_HARD_QUOTA", "storage.hardQuotaBytes"),
+    ("SCHEDULER_AGING_INTERVAL_MS", "scheduler.agingIntervalMs"),
+];
+
+/// Environment name and settings key of each provider, e.g.
+/// `ESAF_OLLAMA_BASE_URL` overrides `llm.providers.ollama.baseUrl`.
+const PROVIDER_ENV: &[(&str, &str)] = &[
+    ("GOOGLE_GENAI", "googleGenai"),
+    ("OPENAI", "openai"),
+    ("ANTHROPIC", "anthropic"),
+    ("OLLAMA", "ollama"),
+    ("LM_STUDIO", "lmStudio"),
+];
+
+const PROVIDER_FIELD_ENV: &[(&str, &str)] = &[
+    ("BASE_URL", "baseUrl"),
+    ("MODEL", "model"),
+    ("TEMPERATURE", "temperature"),
+    ("MAX_TOKENS", "maxTokens"),
+    ("TIMEOUT", "timeoutMs"),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    #[default]
+    System,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LlmProvider {
+    #[default]
+    GoogleGenai,
+    Openai,
+    Anthropic,
+    Ollama,
+    LmStudio,
+}
+
+impl LlmProvider {
+    pub const ALL: [LlmProvider; 5] = [
+        LlmProvider::GoogleGenai,
+        LlmProvider::Openai,
+        LlmProvider::Anthropic,
+        LlmProvider::Ollama,
+        LlmProvider::LmStudio,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LlmProvider::GoogleGenai => "google-genai",
+            LlmProvider::Openai => "openai",
+            LlmProvider::Anthropic => "anthropic",
+            LlmProvider::Ollama => "ollama",
+            LlmProvider::LmStudio => "lm-studio",
+        }
+    }
+
+    /// Key of the provider under `llm.providers`.
+    pub fn key(self) -> &'static str {
+        match self {
+            LlmProvider::GoogleGenai => "googleGenai",
+            LlmProvider::Openai => "openai",
+            LlmProvider::Anthropic => "anthropic",
+            LlmProvider::Ollama => "ollama",
+            LlmProvider::LmStudio => "lmStudio",
+        }
+    }
+
+    /// Environment variable holding the provider's API key, e.g.
+    /// `ESAF_OPENAI_API_KEY`. API keys are not settings; see `vault`.
+    pub fn api_key_variable(self) -> String {
+        let (name, _) = PROVIDER_ENV
+            .iter()
+            .find(|(_, key)| *key == self.key())
+            .expect("every provider has an environment name");
+        format!("{ENV_PREFIX}{name}_API_KEY")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderSettings {
+    pub base_url: String,
+    pub model: String,
+    pub temperature: f64,
+    pub max_tokens: u32,
+    pub timeout_ms: u64,
+}
+
+impl ProviderSettings {
+    fn new(base_url: &str, model: &str, timeout_ms: u64) -> Self {
+        ProviderSettings {
+            base_url: base_url.to_string(),
+            model: model.to_string(),
+            temperature: 0.7,
+            max_tokens: 8164,
+            timeout_ms,
+        }
+    }
+
+    fn validate(&self, field: &str, errors: &mut Vec<FieldError>) {
+        if !(self.base_url.starts_with("http://") || self.base_url.starts_with("https://")) {
+            errors.push(FieldError::new(
+                format!("{field}.baseUrl"),
+                "must be an http:// or https:// URL",
+            ));
+        }
+        if self.model.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("{field}.model"),
+                "must not be empty",
+            ));
+        }
+        if !(0.0..=2.0).contains(&self.temperature) {
+            errors.push(FieldError::new(
+                format!("{field}.temperature"),
+                "must be between 0 and 2",
+            ));
+        }
+        if self.max_tokens == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.maxTokens"),
+                "must be at least 1",
+            ));
+        }
+        if self.timeout_ms == 0 {
+            errors.push(FieldError::new(
+                format!("{field}.timeoutMs"),
+                "must be at least 1",
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderSettingsMap {
+    pub google_genai: ProviderSettings,
+    pub openai: ProviderSettings,
+    pub anthropic: ProviderSettings,
+    pub ollama: ProviderSettings,
+    pub lm_studio: ProviderSettings,
+}
+
+impl ProviderSettingsMap {
+    pub fn get(&self, provider: LlmProvider) -> &ProviderSettings {
+        match provider {
+            LlmProvider::GoogleGenai => &self.google_genai,
+            LlmProvider::Openai => &self.openai,
+            LlmProvider::Anthropic => &self.anthropic,
+            LlmProvider::Ollama => &self.ollama,
+            LlmProvider::LmStudio => &self.lm_studio,
+        }
+    }
+
+    /// Each provider's settings with its key.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &ProviderSettings)> {
+        [
+            ("googleGenai", &self.google_genai),
+            ("openai", &self.openai),
+            ("anthropic", &self.anthropic),
+            ("ollama", &self.ollama),
+            ("lmStudio", &self.lm_studio),
+        ]
+        .into_iter()
+    }
+}
+
+impl Default for ProviderSettingsMap {
+    fn default() -> Self {
+        ProviderSettingsMap {
+            google_genai: ProviderSettings::new(
+                "https://generativelanguage.googleapis.com/v1beta",
+                "gemini-2.0-flash",
+                60_000,
+            ),
+            openai: ProviderSettings::new("https://api.openai.com/v1", "gpt-4o", 60_000),
+            anthropic: ProviderSettings::new(
+                "https://api.anthropic.com/v1",
+                "claude-sonnet-4-20250514",
+                60_000,
+            ),
+            ollama: ProviderSettings::new("http://localhost:11434", "qwen3", 30_000),
+            lm_studio: ProviderSettings::new("http://localhost:1234/v1", "local-model", 30_000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmSettings {
+    pub default_provider: LlmProvider,
+    pub max_concurrent_requests: u32,
+    pub request_timeout_ms: u64,
+    pub enable_caching: bool,
+    pub cache_duration_ms: u64,
+    pub debug_logging: bool,
+    pub mock_responses: bool,
+    pub mock_response_delay_ms: u64,
+    pub providers: ProviderSettingsMap,
+}
+
+impl Default for LlmSettings {
+    fn default() -> Self {
+        LlmSettings {
+            default_provider: LlmProvider::default(),
+            max_concurrent_requests: 3,
+            request_timeout_ms: 60_000,
+            enable_caching: false,
+            cache_duration_ms: 60 * 60 * 1000,
+            debug_logging: false,
+            mock_responses: false,
+            mock_response_delay_ms: 1000,
+            providers: ProviderSettingsMap::default(),
+        }
+    }
+}
+
+/// Limits on the disk space used by the whole installation, kept in
+/// `SETTINGS_FILE` so they do not change with the active workspace. Zero
+/// disables a limit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageSettings {
+    /// Usage past which a warning is emitted on `STORAGE_CHANNEL`.
+    pub soft_quota_bytes: u64,
+    /// Usage past which writes that add data are refused.
+    pub hard_quota_bytes: u64,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        StorageSettings {
+            soft_quota_bytes: 1024 * 1024 * 1024,
+            hard_quota_bytes: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulerSettings {
+    /// Time a pending task waits to gain one priority level.
+    pub aging_interval_ms: i64,
+}
+
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        SchedulerSettings {
+            aging_interval_ms: DEFAULT_AGING_INTERVAL_MS,
+        }
+    }
+}
+
+/// Every setting the backend and frontend share, with the defaults used when
+/// nothing else is configured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    pub theme: Theme,
+    /// How long removed tasks are kept before being purged.
+    pub task_retention_ms: i64,
+    pub llm: LlmSettings,
+    pub storage: StorageSettings,
+    pub scheduler: SchedulerSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            theme: Theme::default(),
+            task_retention_ms: DEFAULT_TASK_RETENTION_MS,
+            llm: LlmSettings::default(),
+            storage: StorageSettings::default(),
+            scheduler: SchedulerSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Checks the ranges serde cannot express, naming each offending field
+    /// by its dotted path, e.g. `llm.providers.ollama.baseUrl`.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.task_retention_ms < 0 {
+            errors.push(FieldError::new("taskRetentionMs", "must not be negative"));
+        }
+        if self.llm.max_concurrent_requests == 0 {
+            errors.push(FieldError::new(
+                "llm.maxConcurrentRequests",
+                "must be at least 1",
+            ));
+        }
+        if self.llm.request_timeout_ms == 0 {
+            errors.push(FieldError::new(
+                "llm.requestTimeoutMs",
+                "must be at least 1",
+            ));
+        }
+        if self.scheduler.aging_interval_ms <= 0 {
+            errors.push(FieldError::new(
+                "scheduler.agingIntervalMs",
+                "must be at least 1",
+            ));
+        }
+        for (key, provider) in self.llm.providers.iter() {
+            provider.validate(&format!("llm.providers.{key}"), &mut errors);
+        }
+        errors
+    }
+
+    /// Layers `layers`, lowest first, and then the environment over the
+    /// defaults. Values that fail validation are skipped and reported in
+    /// [`ResolvedSettings::ignored`] instead of failing the whole load.
+    pub fn resolve(
+        layers: &[&Map<String, Value>],
+        env: impl Fn(&str) -> Option<String>,
+    ) -> ResolvedSettings {
+        let defaults = to_value(&Settings::default());
+        let mut value = defaults.clone();
+        let mut ignored = Vec::new();
+
+        for layer in layers {
+            for (field, stored) in leaves(&Value::Object((*layer).clone())) {
+                match check_field(&defaults, &field, &stored) {
+                    Ok(()) => set_field(&mut value, &field, stored),
+                    Err(message) => ignored.push(FieldError { field, message }),
+                }
+            }
+        }
+
+        let mut environment = Vec::new();
+        for (variable, field) in env_fields() {
+            let Some(raw) = env(&variable) else {
+                continue;
+            };
+            let parsed = parse_env(get_field(&defaults, &field), &raw)
+                .and_then(|parsed| check_field(&defaults, &field, &parsed).map(|()| parsed));
+            match parsed {
+                Ok(parsed) => {
+                    set_field(&mut value, &field, parsed);
+                    environment.push(EnvOverride { field, variable });
+                }
+                Err(message) => ignored.push(FieldError {
+                    field,
+                    message: format!("{variable}: {message}"),
+                }),
+            }
+        }
+
+        ResolvedSettings {
+            settings: serde_json::from_value(value)
+                .expect("every layered value was checked against the defaults"),
+            environment,
+            ignored,
+        }
+    }
+
+    /// The stored form of these settings: only the values that differ from
+    /// `base`, so later builds can change the defaults, and the settings
+    /// file the values, of anything the user never touched.
+    fn stored(&self, base: &Settings) -> Map<String, Value> {
+        match overrides(&to_value(base), &to_value(self)) {
+            Some(Value::Object(stored)) => stored,
+            _ => Map::new(),
+        }
+    }
+}
+
+/// The layer read from `SETTINGS_FILE`, in the same form as the stored
+/// settings of a workspace.
+pub struct SettingsFile {
+    path: PathBuf,
+    values: Map<String, Value>,
+}
+
+impl SettingsFile {
+    /// Reads the settings file in `root`; a missing file is an empty layer.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let path = root.join(SETTINGS_FILE);
+        let values = if path.exists() {
+            let json = fs::read(&path).map_err(|e| e.to_string())?;
+            serde_json::from_slice(&json)
+                .map_err(|e| format!("{} is corrupt: {e}", path.display()))?
+        } else {
+            Map::new()
+        };
+        Ok(SettingsFile { path, values })
+    }
+
+    pub fn values(&self) -> &Map<String, Value> {
+        &self.values
+    }
+
+    /// Replaces the installation-wide settings with those of `settings`,
+    /// keeping everything else in the file as it is.
+    fn save_installation_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        let mut values = self.values.clone();
+        let mut changed = settings.stored(&Settings::default());
+        for key in INSTALLATION_SETTINGS {
+            match changed.remove(*key) {
+                Some(value) => values.insert(key.to_string(), value),
+                None => values.remove(*key),
+            };
+        }
+        if values == self.values {
+            return Ok(());
+        }
+
+        let json = serde_json::to_vec_pretty(&values).map_err(|e| e.to_string())?;
+        let partial = self.path.with_file_name(format!("{SETTINGS_FILE}.partial"));
+        fs::write(&partial, json).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &self.path).map_err(|e| e.to_string())?;
+        self.values = values;
+        Ok(())
+    }
+}
+
+/// `stored` without the installation-wide settings, which a workspace
+/// cannot override.
+fn workspace_layer(mut stored: Map<String, Value>) -> Map<String, Value> {
+    for key in INSTALLATION_SETTINGS {
+        stored.remove(*key);
+    }
+    stored
+}
+
+/// The effective settings and where they deviate from what was saved.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedSettings {
+    pub settings: Settings,
+    /// Fields set by the environment, which saving cannot change.
+    pub environment: Vec<EnvOverride>,
+    /// Stored or environment values that were invalid and left out.
+    pub ignored: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvOverride {
+    pub field: String,
+    pub variable: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: impl Into<String>, message: &str) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Errors returned by `save_settings`, serialized with a `kind` tag so the
+/// frontend can show invalid fields next to their inputs.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SettingsError {
+    Invalid { errors: Vec<FieldError> },
+    Storage { message: String },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Invalid { errors } => {
+                let fields: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{} {}", error.field, error.message))
+                    .collect();
+                write!(f, "invalid settings: {}", fields.join("; "))
+            }
+            SettingsError::Storage { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<rusqlite::Error> for SettingsError {
+    fn from(error: rusqlite::Error) -> Self {
+        SettingsError::Storage {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<String> for SettingsError {
+    fn from(message: String) -> Self {
+        SettingsError::Storage { message }
+    }
+}
+
+fn to_value(settings: &Settings) -> Value {
+    serde_json::to_value(settings).expect("settings always serialize")
+}
+
+/// Every overridable field with its environment variable.
+fn env_fields() -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = ENV_FIELDS
+        .iter()
+        .map(|(suffix, field)| (format!("{ENV_PREFIX}{suffix}"), field.to_string()))
+        .collect();
+    for (provider_env, provider) in PROVIDER_ENV {
+        for (suffix, field) in PROVIDER_FIELD_ENV {
+            fields.push((
+                format!("{ENV_PREFIX}{provider_env}_{suffix}"),
+                format!("llm.providers.{provider}.{field}"),
+            ));
+        }
+    }
+    fields
+}
+
+/// Converts an environment string to the JSON type of the field's default.
+fn parse_env(default: Option<&Value>, raw: &str) -> Result<Value, String> {
+    let raw = raw.trim();
+    match default {
+        Some(Value::Bool(_)) => match raw.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(Value::Bool(true)),
+            "false" | "0" | "no" => Ok(Value::Bool(false)),
+            _ => Err(format!("expected true or false, got {raw:?}")),
+        },
+        Some(Value::Number(_)) => raw
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| raw.parse::<f64>().map(Value::from))
+            .map_err(|_| format!("expected a number, got {raw:?}")),
+        _ => Ok(Value::String(raw.to_string())),
+    }
+}
+
+/// Checks a single value by placing it into the defaults and validating the
+/// result, so the error names exactly that field.
+fn check_field(defaults: &Value, field: &str, value: &Value) -> Result<(), String> {
+    let mut candidate = defaults.clone();
+    set_field(&mut candidate, field, value.clone());
+    let settings: Settings = serde_json::from_value(candidate).map_err(|e| e.to_string())?;
+    match settings.validate().into_iter().next() {
+        Some(error) => Err(error.message),
+        None => Ok(()),
+    }
+}
+
+fn get_field<'a>(value: &'a Value, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(value, |value, key| value.as_object()?.get(key))
+}
+
+fn set_field(value: &mut Value, field: &str, new: Value) {
+    let mut target = value;
+    for key in field.split('.') {
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        target = target
+            .as_object_mut()
+            .expect("made an object above")
+            .entry(key)
+            .or_insert(Value::Null);
+    }
+    *target = new;
+}
+
+/// Dotted paths and values of every non-object value in `value`.
+fn leaves(value: &Value) -> Vec<(String, Value)> {
+    fn walk(value: &Value, prefix: &str, out: &mut Vec<(String, Value)>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    let field = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{prefix}.{key}")
+                    };
+                    walk(value, &field, out);
+                }
+            }
+            _ => out.push((prefix.to_string(), value.clone())),
+        }
+    }
+    let mut out = Vec::new();
+    walk(value, "", &mut out);
+    out
+}
+
+/// The parts of `value` that differ from `defaults`, or `None` if nothing
+/// does.
+fn overrides(defaults: &Value, value: &Value) -> Option<Value> {
+    match (defaults, value) {
+        (Value::Object(defaults), Value::Object(map)) => {
+            let changed: Map<String, Value> = map
+                .iter()
+                .filter_map(|(key, value)| {
+                    let changed = match defaults.get(key) {
+                        Some(default) => overrides(default, value)?,
+                        None => value.clone(),
+                    };
+                    Some((key.clone(), changed))
+                })
+                .collect();
+            (!changed.is_empty()).then_some(Value::Object(changed))
+        }
+        _ if defaults == value => None,
+        _ => Some(value.clone()),
+    }
+}
+
+/// Rewrites the flat keys saved before settings were typed into their place
+/// in [`Settings`]. Keys already in the typed layout are kept; anything else
+/// is dropped.
+pub fn upgrade_legacy_settings(stored: &Map<String, Value>) -> Map<String, Value> {
+    let mut upgraded = Value::Object(Map::new());
+    for key in ["theme", "taskRetentionMs", "llm"] {
+        if let Some(value) = stored.get(key) {
+            set_field(&mut upgraded, key, value.clone());
+        }
+    }
+
+    let provider = stored
+        .get("defaultProvider")
+        .and_then(|value| serde_json::from_value::<LlmProvider>(value.clone()).ok());
+    if provider.is_some() {
+        set_field(
+            &mut upgraded,
+            "llm.defaultProvider",
+            stored["defaultProvider"].clone(),
+        );
+    }
+    let provider = provider.unwrap_or_default().key();
+    let renames = [
+        ("requestTimeout", "llm.requestTimeoutMs".to_string()),
+        ("defaultModel", format!("llm.providers.{provider}.model")),
+        (
+            "temperature",
+            format!("llm.providers.{provider}.temperature"),
+        ),
+        ("maxTokens", format!("llm.providers.{provider}.maxTokens")),
+    ];
+    for (legacy, field) in renames {
+        if let Some(value) = stored.get(legacy) {
+            set_field(&mut upgraded, &field, value.clone());
+        }
+    }
+
+    match upgraded {
+        Value::Object(upgraded) => upgraded,
+        _ => unreachable!("built from an object"),
+    }
+}
+
+impl Store {
+    /// The saved settings layer, holding only values that differ from the
+    /// defaults.
+    pub fn stored_settings(&self) -> rusqlite::Result<Map<String, Value>> {
+        let mut stmt = self.conn.prepare("SELECT key, value FROM settings")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, json_column(row, "value")?)))?;
+        rows.collect()
+    }
+
+    /// Replaces all stored settings with `settings`.
+    pub fn replace_settings(&self, settings: &Map<String, Value>) -> rusqlite::Result<()> {
+        self.conn.execute("DELETE FROM settings", [])?;
+        for (key, value) in settings {
+            self.conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)",
+                params![key, to_json(value)?],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl AppState {
+    /// The effective settings: defaults, then `SETTINGS_FILE`, then the
+    /// active workspace's stored layer, then the process environment.
+    pub fn settings(&self) -> rusqlite::Result<ResolvedSettings> {
+        let stored = workspace_layer(self.store.stored_settings()?);
+        Ok(Settings::resolve(
+            &[self.settings_file.values(), &stored],
+            |variable| std::env::var(variable).ok(),
+        ))
+    }
+}
+
+/// Tells every window to reload the effective settings.
+pub fn emit_settings(app: &AppHandle, state: &AppState) -> Result<(), String> {
+    let resolved = state.settings().map_err(|e| e.to_string())?;
+    app.emit(SETTINGS_CHANNEL, &resolved)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_settings(state: State<AppStateType>) -> Result<ResolvedSettings, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.settings().map_err(|e| e.to_string())
+}
+
+/// Saves validated `settings`: installation-wide fields to `SETTINGS_FILE`,
+/// the rest to the active workspace. Fields set by the environment keep
+/// their previously saved value, since the environment would mask them
+/// anyway.
+fn save(state: &mut AppState, settings: Settings) -> Result<(), SettingsError> {
+    let file = state.settings_file.values().clone();
+    let stored = workspace_layer(state.store.stored_settings()?);
+    let current = state.settings()?;
+    let saved = Settings::resolve(&[&file, &stored], |_| None).settings;
+    let base = Settings::resolve(&[&file], |_| None).settings;
+
+    let mut value = to_value(&settings);
+    for EnvOverride { field, .. } in &current.environment {
+        if let Some(saved) = get_field(&to_value(&saved), field) {
+            set_field(&mut value, field, saved.clone());
+        }
+    }
+    let settings: Settings = serde_json::from_value(value).map_err(|e| e.to_string())?;
+
+    // The file is replaced atomically, so writing it first leaves both
+    // layers as they were if it fails.
+    state.settings_file.save_installation_settings(&settings)?;
+    state.commit(Mutation::ReplaceSettings {
+        settings: workspace_layer(settings.stored(&base)),
+    })?;
+    Ok(())
+}
+
+/// Validates and saves `settings`, returning the effective settings.
+#[tauri::command]
+pub fn save_settings(
+    settings: Settings,
+    app: AppHandle,
+    state: State<AppStateType>,
+) -> Result<ResolvedSettings, SettingsError> {
+    let errors = settings.validate();
+    if !errors.is_empty() {
+        return Err(SettingsError::Invalid { errors });
+    }
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    save(&mut app_state, settings)?;
+    emit_settings(&app, &app_state)?;
+    storage::refresh_quota(&app, &mut app_state)?;
+    Ok(app_state.settings()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn layer(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn layers_apply_defaults_file_workspace_then_environment() {
+        let file = layer(
+            json!({ "theme": "dark", "taskRetentionMs": 10, "llm": { "enableCaching": true } }),
+        );
+        let stored = layer(json!({ "taskRetentionMs": 20, "llm": { "mockResponses": true } }));
+        let resolved = Settings::resolve(&[&file, &stored], |variable| {
+            (variable == "ESAF_MOCK_LLM_RESPONSES").then(|| "false".to_string())
+        });
+
+        let settings = resolved.settings;
+        assert_eq!(settings.theme, Theme::Dark);
+        assert_eq!(settings.task_retention_ms, 20);
+        assert!(settings.llm.enable_caching);
+        assert!(!settings.llm.mock_responses);
+        assert_eq!(settings.llm.max_concurrent_requests, 3);
+        assert_eq!(resolved.environment[0].field, "llm.mockResponses");
+    }
+
+    #[test]
+    fn installation_settings_are_saved_to_the_file_only() {
+        let dir = std::env::temp_dir().join(format!("esaf-settings-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(SETTINGS_FILE), r#"{ "theme": "dark" }"#).unwrap();
+        let mut file = SettingsFile::load(&dir).unwrap();
+
+        let mut settings = Settings::default();
+        settings.storage.hard_quota_bytes = 5;
+        file.save_installation_settings(&settings).unwrap();
+
+        let expected = layer(json!({ "theme": "dark", "storage": { "hardQuotaBytes": 5 } }));
+        assert_eq!(SettingsFile::load(&dir).unwrap().values(), &expected);
+        let stored = workspace_layer(settings.stored(&Settings::default()));
+        assert!(!stored.contains_key("storage"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_settings_file_write_leaves_the_workspace_unchanged() {
+        let root = crate::test_support::temp_dir("settings");
+        let mut state = crate::test_support::app_state(&root);
+        // A directory in the way of the partial file makes the write fail.
+        fs::create_dir(root.join(format!("{SETTINGS_FILE}.partial"))).unwrap();
+
+        let mut settings = Settings {
+            theme: Theme::Dark,
+            ..Settings::default()
+        };
+        settings.storage.hard_quota_bytes = 5;
+        assert!(save(&mut state, settings.clone()).is_err());
+        assert!(state.store.stored_settings().unwrap().is_empty());
+        assert!(!root.join(SETTINGS_FILE).exists());
+
+        fs::remove_dir(root.join(format!("{SETTINGS_FILE}.partial"))).unwrap();
+        save(&mut state, settings).unwrap();
+        let resolved = state.settings().unwrap().settings;
+        assert_eq!(resolved.theme, Theme::Dark);
+        assert_eq!(resolved.storage.hard_quota_bytes, 5);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
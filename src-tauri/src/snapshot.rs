@@ -0,0 +1,485 @@
+use crate::agents::AgentInfo;
+use crate::chats::{ChatMessage, ChatSession};
+use crate::documents::{BlobStore, DocumentEntry, DocumentSource};
+use crate::events::ESAFEvent;
+use crate::journal::Mutation;
+use crate::lifecycle::TaskTransition;
+use crate::results::AnalysisResult;
+use crate::settings::{self, upgrade_legacy_settings};
+use crate::storage;
+use crate::store::{now_millis, Store};
+use crate::task_graph::TaskGraph;
+use crate::tasks::Task;
+use crate::tombstones::RemovedTask;
+use crate::AppStateType;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+/// Marker identifying ESAF snapshot files.
+pub const SNAPSHOT_FORMAT: &str = "esaf-snapshot";
+
+/// Version written by this build. Older versions are read, newer refused.
+pub const SNAPSHOT_VERSION: u32 = 5;
+
+/// Complete persisted state of the backend, as written to a snapshot file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: i64,
+    pub tasks: Vec<Task>,
+    /// Tombstones, added in version 2.
+    #[serde(default)]
+    pub removed_tasks: Vec<RemovedTask>,
+    pub task_history: Vec<TaskTransition>,
+    pub agents: Vec<AgentInfo>,
+    pub events: Vec<ESAFEvent>,
+    pub results: Vec<AnalysisResult>,
+    /// Stored settings layer; flat untyped keys before version 5.
+    pub settings: Map<String, Value>,
+    /// Chat history, added in version 3.
+    #[serde(default)]
+    pub chat_sessions: Vec<ChatSession>,
+    #[serde(default)]
+    pub chat_messages: Vec<ChatMessage>,
+    /// Document library including bodies, added in version 4.
+    #[serde(default)]
+    pub documents: Vec<DocumentSource>,
+}
+
+impl Snapshot {
+    /// Checks that the snapshot is one this build understands and that its
+    /// contents would pass the same validation as the individual commands.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.format != SNAPSHOT_FORMAT {
+            return Err(format!("not an ESAF snapshot (format {:?})", self.format));
+        }
+        if self.version > SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} is newer than the supported version {SNAPSHOT_VERSION}",
+                self.version
+            ));
+        }
+
+        let mut tasks = HashMap::new();
+        for task in &self.tasks {
+            task.validate()?;
+            if tasks.insert(task.id.clone(), task.clone()).is_some() {
+                return Err(format!("snapshot lists task {} more than once", task.id));
+            }
+        }
+        for task in &self.tasks {
+            if let Some(missing) = task
+                .dependencies
+                .iter()
+                .find(|dependency| !tasks.contains_key(*dependency))
+            {
+                return Err(format!(
+                    "task {} depends on unknown task {missing}",
+                    task.id
+                ));
+            }
+        }
+        TaskGraph::new(&tasks).topological_order()?;
+
+        let mut removed_ids = HashSet::new();
+        let mut known = tasks.clone();
+        for removed in &self.removed_tasks {
+            removed.task.validate()?;
+            if tasks.contains_key(&removed.task.id) || !removed_ids.insert(&removed.task.id) {
+                return Err(format!(
+                    "snapshot lists task {} more than once",
+                    removed.task.id
+                ));
+            }
+            known.insert(removed.task.id.clone(), removed.task.clone());
+        }
+        // A tombstone may wait on live tasks or on other tombstones undone
+        // before it, so `undo_remove_task` can always bring it back.
+        for removed in &self.removed_tasks {
+            if let Some(missing) = removed
+                .task
+                .dependencies
+                .iter()
+                .find(|dependency| !known.contains_key(*dependency))
+            {
+                return Err(format!(
+                    "removed task {} depends on unknown task {missing}",
+                    removed.task.id
+                ));
+            }
+        }
+        TaskGraph::new(&known).topological_order()?;
+
+        if let Some(transition) = self.task_history.iter().find(|transition| {
+            !tasks.contains_key(&transition.task_id) && !removed_ids.contains(&transition.task_id)
+        }) {
+            return Err(format!(
+                "task history refers to unknown task {}",
+                transition.task_id
+            ));
+        }
+
+        let mut agent_ids = HashSet::new();
+        for agent in &self.agents {
+            agent.validate()?;
+            if !agent_ids.insert(&agent.id) {
+                return Err(format!("snapshot lists agent {} more than once", agent.id));
+            }
+        }
+
+        let mut event_ids = HashSet::new();
+        for event in &self.events {
+            event.validate()?;
+            if !event_ids.insert(&event.id) {
+                return Err(format!("snapshot lists event {} more than once", event.id));
+            }
+        }
+
+        let mut result_ids = HashSet::new();
+        for result in &self.results {
+            result.validate()?;
+            if !result_ids.insert(&result.id) {
+                return Err(format!(
+                    "snapshot lists result {} more than once",
+                    result.id
+                ));
+            }
+        }
+
+        let mut session_ids = HashSet::new();
+        for session in &self.chat_sessions {
+            session.validate()?;
+            if !session_ids.insert(&session.id) {
+                return Err(format!(
+                    "snapshot lists chat session {} more than once",
+                    session.id
+                ));
+            }
+        }
+
+        let mut message_ids = HashSet::new();
+        for message in &self.chat_messages {
+            message.validate()?;
+            if !session_ids.contains(&message.session_id) {
+                return Err(format!(
+                    "chat message {} belongs to unknown session {}",
+                    message.id, message.session_id
+                ));
+            }
+            if !message_ids.insert(&message.id) {
+                return Err(format!(
+                    "snapshot lists chat message {} more than once",
+                    message.id
+                ));
+            }
+        }
+
+        let mut document_ids = HashSet::new();
+        for document in &self.documents {
+            document.validate()?;
+            if !document_ids.insert(&document.id) {
+                return Err(format!(
+                    "snapshot lists document {} more than once",
+                    document.id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Store {
+    /// Collects all persisted state, reading document bodies from `blobs`.
+    pub fn snapshot(&self, blobs: &BlobStore) -> Result<Snapshot, String> {
+        let documents = self
+            .documents()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|entry| blobs.load(entry))
+            .collect::<Result<_, _>>()?;
+        self.collect_snapshot(documents).map_err(|e| e.to_string())
+    }
+
+    fn collect_snapshot(&self, documents: Vec<DocumentSource>) -> rusqlite::Result<Snapshot> {
+        let mut tasks: Vec<Task> = self.tasks()?.into_values().collect();
+        tasks.sort_by(|a, b| {
+            a.created_at
+                .cmp(&b.created_at)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        let mut agents: Vec<AgentInfo> = self.agents()?.into_values().collect();
+        agents.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(Snapshot {
+            format: SNAPSHOT_FORMAT.to_string(),
+            version: SNAPSHOT_VERSION,
+            exported_at: now_millis(),
+            tasks,
+            removed_tasks: self.removed_tasks()?,
+            task_history: self.transitions()?,
+            agents,
+            events: self.recent_events(None)?,
+            results: self.results(None)?,
+            settings: self.stored_settings()?,
+            chat_sessions: self.chat_sessions()?,
+            chat_messages: self.chat_messages()?,
+            documents,
+        })
+    }
+
+    /// Replaces all persisted state with `snapshot` and the library with
+    /// `documents`, whose bodies are in the blob store. Runs inside the
+    /// transaction opened by [`Store::apply_journaled`].
+    pub fn restore(
+        &self,
+        snapshot: &Snapshot,
+        documents: &[DocumentEntry],
+    ) -> rusqlite::Result<()> {
+        self.conn.execute_batch(
+            "DELETE FROM task_transitions;
+             DELETE FROM tasks;
+             DELETE FROM agents;
+             DELETE FROM events;
+             DELETE FROM results;
+             DELETE FROM chat_sessions;
+             DELETE FROM documents;",
+        )?;
+        self.clear_search_index()?;
+
+        for task in &snapshot.tasks {
+            self.insert_task(task)?;
+        }
+        for removed in &snapshot.removed_tasks {
+            self.insert_task(&removed.task)?;
+            self.tombstone_task(
+                &removed.task.id,
+                removed.removed_at,
+                removed.removed_by.as_deref(),
+            )?;
+        }
+        for transition in &snapshot.task_history {
+            self.insert_transition(transition)?;
+        }
+        for agent in &snapshot.agents {
+            self.upsert_agent(agent)?;
+        }
+        for event in &snapshot.events {
+            self.insert_event(event)?;
+        }
+        for result in &snapshot.results {
+            self.insert_result(result)?;
+        }
+        for session in &snapshot.chat_sessions {
+            self.save_chat_session(session)?;
+        }
+        for message in &snapshot.chat_messages {
+            self.insert_chat_message(message)?;
+        }
+        for document in documents {
+            self.save_document(document)?;
+        }
+        if snapshot.version < 5 {
+            self.replace_settings(&upgrade_legacy_settings(&snapshot.settings))
+        } else {
+            self.replace_settings(&snapshot.settings)
+        }
+    }
+}
+
+/// Writes `snapshot` next to `path` first and renames it into place, so an
+/// interrupted export never leaves a truncated file behind.
+fn write_snapshot(path: &Path, snapshot: &Snapshot) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(snapshot).map_err(|e| e.to_string())?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    fs::write(&partial, json).map_err(|e| e.to_string())?;
+    fs::rename(&partial, path).map_err(|e| e.to_string())
+}
+
+/// Moves the document bodies of `snapshot` into `blobs`, leaving a
+/// restore that journals only their hashes.
+fn restore_mutation(mut snapshot: Snapshot, blobs: &BlobStore) -> Result<Mutation, String> {
+    let documents = snapshot
+        .documents
+        .drain(..)
+        .map(|document| {
+            blobs.put(document.content.as_bytes())?;
+            Ok(document.entry())
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Mutation::RestoreSnapshot {
+        snapshot: Box::new(snapshot),
+        documents,
+    })
+}
+
+fn read_snapshot(path: &Path) -> Result<Snapshot, String> {
+    let json = fs::read(path).map_err(|e| e.to_string())?;
+    let snapshot: Snapshot = serde_json::from_slice(&json)
+        .map_err(|e| format!("{} is not a readable snapshot: {e}", path.display()))?;
+    snapshot.validate()?;
+    Ok(snapshot)
+}
+
+/// Exports the full backend state to `path`, asking the user for a location
+/// when none is given. Returns the written path, or `None` if the user
+/// cancelled the dialog.
+#[tauri::command]
+pub async fn export_snapshot(
+    path: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+) -> Result<Option<String>, String> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let chosen = app
+                .dialog()
+                .file()
+                .add_filter("ESAF snapshot", &["json"])
+                .set_file_name("esaf-snapshot.json")
+                .blocking_save_file();
+            match chosen {
+                Some(chosen) => chosen.into_path().map_err(|e| e.to_string())?,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let snapshot = {
+        let app_state = state.lock().map_err(|e| e.to_string())?;
+        let blobs = BlobStore::new(&app_state.workspaces.active_dir());
+        app_state.store.snapshot(&blobs)?
+    };
+    write_snapshot(&path, &snapshot)?;
+    Ok(Some(path.display().to_string()))
+}
+
+/// Replaces the full backend state with the snapshot at `path`, asking the
+/// user for a file when none is given. Returns the restored path, or `None`
+/// if the user cancelled the dialog.
+#[tauri::command]
+pub async fn import_snapshot(
+    path: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+) -> Result<Option<String>, String> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let chosen = app
+                .dialog()
+                .file()
+                .add_filter("ESAF snapshot", &["json"])
+                .blocking_pick_file();
+            match chosen {
+                Some(chosen) => chosen.into_path().map_err(|e| e.to_string())?,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let snapshot = read_snapshot(&path)?;
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.quota.check_writable()?;
+    let blobs = BlobStore::new(&app_state.workspaces.active_dir());
+    let restore = restore_mutation(snapshot, &blobs)?;
+    app_state.commit(restore)?;
+
+    // The restored state supersedes everything journaled before it.
+    let app_state = &mut *app_state;
+    app_state.journal.compact(&app_state.store)?;
+    blobs.collect_garbage(&app_state.store)?;
+    settings::emit_settings(&app, app_state)?;
+    storage::refresh_quota(&app, app_state)?;
+    Ok(Some(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, task, temp_dir};
+    use serde_json::json;
+
+    fn snapshot(tasks: Vec<Task>, removed: Vec<Task>) -> Snapshot {
+        let mut snapshot: Snapshot = serde_json::from_value(json!({
+            "format": SNAPSHOT_FORMAT,
+            "version": SNAPSHOT_VERSION,
+            "exportedAt": 0,
+            "tasks": [],
+            "taskHistory": [],
+            "agents": [],
+            "events": [],
+            "results": [],
+            "settings": {},
+        }))
+        .unwrap();
+        snapshot.tasks = tasks;
+        snapshot.removed_tasks = removed
+            .into_iter()
+            .map(|task| RemovedTask {
+                task,
+                removed_at: 0,
+                removed_by: None,
+            })
+            .collect();
+        snapshot
+    }
+
+    #[test]
+    fn tombstones_must_depend_on_known_tasks() {
+        let valid = snapshot(
+            vec![task("live", &[])],
+            vec![task("first", &["live"]), task("second", &["first"])],
+        );
+        valid.validate().unwrap();
+
+        let dangling = snapshot(vec![task("live", &[])], vec![task("gone", &["purged"])]);
+        let error = dangling.validate().unwrap_err();
+        assert_eq!(error, "removed task gone depends on unknown task purged");
+
+        let cycle = snapshot(vec![], vec![task("a", &["b"]), task("b", &["a"])]);
+        assert!(cycle.validate().is_err());
+    }
+
+    #[test]
+    fn restores_journal_document_hashes_not_bodies() {
+        let root = temp_dir("snapshot");
+        let mut state = app_state(&root);
+        let mut restored = snapshot(vec![task("t1", &[])], vec![]);
+        restored.documents = vec![serde_json::from_value(json!({
+            "id": "d1",
+            "name": "notes",
+            "type": "text",
+            "content": "the quarterly churn figures",
+            "metadata": { "created": 1, "lastAccessed": 1 },
+        }))
+        .unwrap()];
+        let hash = restored.documents[0].entry().content_hash;
+
+        let blobs = BlobStore::new(&state.workspaces.active_dir());
+        let restore = restore_mutation(restored, &blobs).unwrap();
+        let journaled = serde_json::to_string(&restore).unwrap();
+        assert!(!journaled.contains("churn"), "{journaled}");
+        assert!(journaled.contains(&hash));
+
+        state.commit(restore).unwrap();
+        let document = state.store.document("d1").unwrap().unwrap();
+        assert_eq!(
+            blobs.content(&document).unwrap(),
+            "the quarterly churn figures"
+        );
+        assert!(state.store.task("t1").unwrap().is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,408 @@
+use crate::documents::BLOBS_DIR;
+use crate::settings::StorageSettings;
+use crate::store::{now_millis, DATABASE_FILE};
+use crate::workspaces::Workspace;
+use crate::{AppState, AppStateType};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Tauri event emitted with the new [`QuotaStatus`] whenever usage crosses a
+/// quota in either direction.
+pub const STORAGE_CHANNEL: &str = "esaf-storage";
+
+/// How often the background check measures disk usage against the quotas.
+const QUOTA_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// What the bytes of a workspace are spent on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageCategory {
+    Tasks,
+    Chats,
+    Documents,
+    Events,
+    Results,
+    /// Data derived from the rest, such as the search index.
+    Caches,
+    /// Agents, settings, the journal, backups and free database pages.
+    Other,
+}
+
+impl StorageCategory {
+    const ALL: [StorageCategory; 7] = [
+        StorageCategory::Tasks,
+        StorageCategory::Chats,
+        StorageCategory::Documents,
+        StorageCategory::Events,
+        StorageCategory::Results,
+        StorageCategory::Caches,
+        StorageCategory::Other,
+    ];
+
+    fn of_table(table: &str) -> Self {
+        match table {
+            "tasks" | "task_transitions" => StorageCategory::Tasks,
+            "chat_sessions" | "chat_messages" => StorageCategory::Chats,
+            "documents" => StorageCategory::Documents,
+            "events" => StorageCategory::Events,
+            "results" => StorageCategory::Results,
+            "search_docs" | "search_postings" => StorageCategory::Caches,
+            _ => StorageCategory::Other,
+        }
+    }
+
+    /// Table, timestamp column and size expression of the rows whose age
+    /// stands for the age of the category.
+    fn aged_rows(self) -> Option<(&'static str, &'static str, &'static str)> {
+        match self {
+            StorageCategory::Tasks => Some((
+                "tasks",
+                "created_at",
+                "LENGTH(payload) + LENGTH(dependencies)",
+            )),
+            StorageCategory::Chats => Some((
+                "chat_messages",
+                "timestamp",
+                "LENGTH(content) + LENGTH(metadata)",
+            )),
+            StorageCategory::Documents => Some(("documents", "created", "COALESCE(size, 0)")),
+            StorageCategory::Events => Some(("events", "timestamp", "LENGTH(payload)")),
+            StorageCategory::Results => {
+                Some(("results", "timestamp", "LENGTH(result) + LENGTH(metadata)"))
+            }
+            StorageCategory::Caches => Some(("search_docs", "timestamp", "LENGTH(body)")),
+            StorageCategory::Other => None,
+        }
+    }
+}
+
+/// Bytes of a category split by the age of the rows they hold. Rows are
+/// weighted by their payload size, so the split is an estimate.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgeBreakdown {
+    pub last_day: u64,
+    pub last_week: u64,
+    pub last_month: u64,
+    pub older: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryUsage {
+    pub category: StorageCategory,
+    pub bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_age: Option<AgeBreakdown>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceUsage {
+    pub workspace_id: String,
+    pub name: String,
+    pub bytes: u64,
+    pub categories: Vec<CategoryUsage>,
+}
+
+/// Disk usage of the installation, as reported by `get_storage_stats`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageStats {
+    pub measured_at: i64,
+    pub total_bytes: u64,
+    /// Bytes outside any workspace, such as the registry and the vault.
+    pub shared_bytes: u64,
+    pub workspaces: Vec<WorkspaceUsage>,
+    pub quota: QuotaStatus,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QuotaLevel {
+    #[default]
+    Normal,
+    /// Past the soft quota.
+    Warning,
+    /// Past the hard quota; writes that add data are refused.
+    Exceeded,
+}
+
+/// Last measured usage against the configured quotas.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaStatus {
+    pub level: QuotaLevel,
+    pub used_bytes: u64,
+    pub soft_quota_bytes: u64,
+    pub hard_quota_bytes: u64,
+    pub checked_at: i64,
+}
+
+impl QuotaStatus {
+    pub fn new(used_bytes: u64, quotas: &StorageSettings, checked_at: i64) -> Self {
+        let past = |quota: u64| quota > 0 && used_bytes >= quota;
+        let level = if past(quotas.hard_quota_bytes) {
+            QuotaLevel::Exceeded
+        } else if past(quotas.soft_quota_bytes) {
+            QuotaLevel::Warning
+        } else {
+            QuotaLevel::Normal
+        };
+        QuotaStatus {
+            level,
+            used_bytes,
+            soft_quota_bytes: quotas.soft_quota_bytes,
+            hard_quota_bytes: quotas.hard_quota_bytes,
+            checked_at,
+        }
+    }
+
+    /// Refuses writes that add data once the hard quota is exceeded.
+    pub fn check_writable(&self) -> Result<(), String> {
+        if self.level == QuotaLevel::Exceeded {
+            return Err(format!(
+                "storage uses {} bytes, past the hard quota of {} bytes; \
+                 delete data or raise storage.hardQuotaBytes",
+                self.used_bytes, self.hard_quota_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Total size of the files under `path`, or 0 if it does not exist.
+pub fn dir_size(path: &Path) -> io::Result<u64> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += dir_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// Measures the workspace in `dir` through a separate read-only connection,
+/// so workspaces other than the active one can be measured without opening
+/// (and migrating) them.
+fn workspace_usage(workspace: &Workspace, dir: &Path, now: i64) -> Result<WorkspaceUsage, String> {
+    let total = dir_size(dir).map_err(|e| e.to_string())?;
+    let mut bytes: HashMap<StorageCategory, u64> = HashMap::new();
+    let mut by_age = HashMap::new();
+
+    let db_path = dir.join(DATABASE_FILE);
+    if db_path.exists() {
+        let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| e.to_string())?;
+        for (table, size) in table_sizes(&conn).map_err(|e| e.to_string())? {
+            *bytes.entry(StorageCategory::of_table(&table)).or_default() += size;
+        }
+        *bytes.entry(StorageCategory::Documents).or_default() +=
+            dir_size(&dir.join(BLOBS_DIR)).map_err(|e| e.to_string())?;
+
+        for category in StorageCategory::ALL {
+            let Some(rows) = category.aged_rows() else {
+                continue;
+            };
+            let size = bytes.get(&category).copied().unwrap_or(0);
+            if let Some(ages) = age_breakdown(&conn, rows, size, now).map_err(|e| e.to_string())? {
+                by_age.insert(category, ages);
+            }
+        }
+    }
+
+    // Whatever the tables and blobs do not account for, including free
+    // pages, the WAL, the journal and migration backups.
+    let categorized: u64 = bytes
+        .iter()
+        .filter(|(category, _)| **category != StorageCategory::Other)
+        .map(|(_, size)| size)
+        .sum();
+    bytes.insert(StorageCategory::Other, total.saturating_sub(categorized));
+
+    Ok(WorkspaceUsage {
+        workspace_id: workspace.id.clone(),
+        name: workspace.name.clone(),
+        bytes: total,
+        categories: StorageCategory::ALL
+            .into_iter()
+            .map(|category| CategoryUsage {
+                category,
+                bytes: bytes.get(&category).copied().unwrap_or(0),
+                by_age: by_age.remove(&category),
+            })
+            .collect(),
+    })
+}
+
+/// Bytes of database pages used by each table, including its indexes.
+fn table_sizes(conn: &Connection) -> rusqlite::Result<Vec<(String, u64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT schema.tbl_name, SUM(stat.pgsize)
+         FROM dbstat AS stat JOIN sqlite_schema AS schema ON schema.name = stat.name
+         GROUP BY schema.tbl_name",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Splits `bytes` by the age of the rows in `table`, or `None` if the table
+/// is missing or holds nothing to weigh by.
+fn age_breakdown(
+    conn: &Connection,
+    (table, timestamp, size): (&str, &str, &str),
+    bytes: u64,
+    now: i64,
+) -> rusqlite::Result<Option<AgeBreakdown>> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_schema WHERE type = 'table' AND name = ?1)",
+        params![table],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(None);
+    }
+
+    let weights: [i64; 4] = conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(CASE WHEN {timestamp} >= ?1 THEN {size} END), 0),
+                    COALESCE(SUM(CASE WHEN {timestamp} < ?1 AND {timestamp} >= ?2 THEN {size} END), 0),
+                    COALESCE(SUM(CASE WHEN {timestamp} < ?2 AND {timestamp} >= ?3 THEN {size} END), 0),
+                    COALESCE(SUM(CASE WHEN {timestamp} < ?3 THEN {size} END), 0)
+             FROM {table}"
+        ),
+        params![now - DAY_MS, now - 7 * DAY_MS, now - 30 * DAY_MS],
+        |row| Ok([row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?]),
+    )?;
+    let weights = weights.map(|weight| weight.max(0) as u128);
+    let total: u128 = weights.iter().sum();
+    if total == 0 {
+        return Ok(None);
+    }
+
+    let share = |weight: u128| (bytes as u128 * weight / total) as u64;
+    let last_day = share(weights[0]);
+    let last_week = share(weights[1]);
+    let last_month = share(weights[2]);
+    Ok(Some(AgeBreakdown {
+        last_day,
+        last_week,
+        last_month,
+        // Rounding leftovers go here, so the buckets add up to `bytes`.
+        older: bytes - last_day - last_week - last_month,
+    }))
+}
+
+/// Records `used_bytes` against the installation-wide quotas and
+/// notifies every window when the quota level changes.
+pub fn check_quota(
+    app: &AppHandle,
+    state: &mut AppState,
+    used_bytes: u64,
+) -> Result<QuotaStatus, String> {
+    let quotas = state
+        .settings()
+        .map_err(|e| e.to_string())?
+        .settings
+        .storage;
+    let status = QuotaStatus::new(used_bytes, &quotas, now_millis());
+    let changed = status.level != state.quota.level;
+    state.quota = status.clone();
+    if changed {
+        app.emit(STORAGE_CHANNEL, &status)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(status)
+}
+
+/// Re-evaluates the last measured usage, for when the quotas may have
+/// changed.
+pub fn refresh_quota(app: &AppHandle, state: &mut AppState) -> Result<QuotaStatus, String> {
+    let used_bytes = state.quota.used_bytes;
+    check_quota(app, state, used_bytes)
+}
+
+/// Periodically measures disk usage against the quotas for as long as the
+/// app runs, starting right away.
+pub fn schedule_quota_checks(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(QUOTA_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let state = app.state::<AppStateType>();
+            let Ok(root) = state
+                .lock()
+                .map(|app_state| app_state.workspaces.root().to_path_buf())
+            else {
+                break;
+            };
+            // Measured without the lock, since walking the blobs can be slow.
+            let used_bytes = match dir_size(&root) {
+                Ok(used_bytes) => used_bytes,
+                Err(e) => {
+                    log::warn!("failed to measure storage: {e}");
+                    continue;
+                }
+            };
+            let Ok(mut app_state) = state.lock() else {
+                break;
+            };
+            if let Err(e) = check_quota(&app, &mut app_state, used_bytes) {
+                log::error!("failed to check storage quotas: {e}");
+            }
+        }
+    });
+}
+
+/// Measures the on-disk usage of every workspace by category and age, and
+/// updates the quota status with the result.
+#[tauri::command]
+pub async fn get_storage_stats(
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+) -> Result<StorageStats, String> {
+    let (root, workspaces) = {
+        let app_state = state.lock().map_err(|e| e.to_string())?;
+        let workspaces: Vec<_> = app_state
+            .workspaces
+            .workspaces()
+            .iter()
+            .map(|workspace| (workspace.clone(), app_state.workspaces.dir(&workspace.id)))
+            .collect();
+        (app_state.workspaces.root().to_path_buf(), workspaces)
+    };
+
+    let now = now_millis();
+    let total_bytes = dir_size(&root).map_err(|e| e.to_string())?;
+    let workspaces = workspaces
+        .iter()
+        .map(|(workspace, dir)| workspace_usage(workspace, dir, now))
+        .collect::<Result<Vec<_>, _>>()?;
+    let workspace_bytes: u64 = workspaces.iter().map(|usage| usage.bytes).sum();
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let quota = check_quota(&app, &mut app_state, total_bytes)?;
+    Ok(StorageStats {
+        measured_at: now,
+        total_bytes,
+        shared_bytes: total_bytes.saturating_sub(workspace_bytes),
+        workspaces,
+        quota,
+    })
+}
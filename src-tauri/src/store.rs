@@ -0,0 +1,58 @@
+use crate::documents::BlobStore;
+use crate::migrations::MigrationError;
+use rusqlite::types::Type;
+use rusqlite::{Connection, Row};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the SQLite database inside the app data directory.
+pub const DATABASE_FILE: &str = "esaf.db";
+
+/// Embedded SQLite store backing the application state.
+pub struct Store {
+    pub(crate) conn: Connection,
+    /// Bodies of the documents indexed in the database.
+    pub(crate) blobs: BlobStore,
+}
+
+impl Store {
+    /// Opens (or creates) the database at `path` and migrates it to the
+    /// current schema version.
+    pub fn open(path: &Path) -> Result<Self, MigrationError> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+
+        let workspace_dir = path.parent().unwrap_or(Path::new(""));
+        let store = Store {
+            conn,
+            blobs: BlobStore::new(workspace_dir),
+        };
+        store.migrate(path)?;
+        Ok(store)
+    }
+}
+
+/// Serializes `value` for storage in a JSON text column.
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Reads and deserializes a JSON text column.
+pub fn json_column<T: DeserializeOwned>(row: &Row, column: &str) -> rusqlite::Result<T> {
+    let text: String = row.get(column)?;
+    serde_json::from_str(&text).map_err(|e| {
+        let index = row.as_ref().column_index(column).unwrap_or_default();
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e))
+    })
+}
+
+/// Current time in milliseconds since the Unix epoch, matching `Date.now()`.
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,477 @@
+use crate::llm::{
+    self, AnthropicMessage, AnthropicUsage, ChatCompletion, GoogleResponse, LlmError, LlmRequest,
+    LlmResponse, LlmService, OllamaChat, ProviderClient, TokenUsage,
+};
+use crate::requests::RequestRegistry;
+use crate::settings::LlmProvider;
+use crate::AppStateType;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::future::Future;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Manager, State};
+
+/// Messages sent over the channel of `stream_completion`, in order: one
+/// `started`, any number of `delta`s, at most one `usage` and one `finished`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "event",
+    content = "data",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum StreamEvent {
+    /// The request was accepted under `request_id`, which `cancel_request`
+    /// takes.
+    Started {
+        request_id: String,
+        provider: LlmProvider,
+        model: String,
+    },
+    /// Text appended to the completion.
+    Delta {
+        text: String,
+    },
+    Usage {
+        usage: TokenUsage,
+    },
+    /// The whole completion, as `generate_completion` would return it.
+    Finished {
+        response: LlmResponse,
+    },
+}
+
+/// Splits a streamed body into lines, holding back a partial last line until
+/// the rest of it arrives.
+#[derive(Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let Some(end) = self.pending.iter().rposition(|&byte| byte == b'\n') else {
+            return Vec::new();
+        };
+        // A newline byte never occurs inside a UTF-8 sequence, so the
+        // complete lines are valid on their own.
+        let complete: Vec<u8> = self.pending.drain(..=end).collect();
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The last line if the body did not end with a newline.
+    pub fn finish(self) -> Option<String> {
+        let rest = String::from_utf8_lossy(&self.pending).trim().to_string();
+        (!rest.is_empty()).then_some(rest)
+    }
+}
+
+/// Completion assembled from the chunks received so far.
+#[derive(Default)]
+struct StreamedCompletion {
+    status: u16,
+    content: String,
+    usage: Option<TokenUsage>,
+    details: Map<String, Value>,
+    /// Anthropic reports the prompt tokens in its first event and the
+    /// completion tokens in a later one.
+    prompt_tokens: u64,
+    /// Whether the provider marked the end of the completion, so a body cut
+    /// short is not taken for a whole one.
+    complete: bool,
+}
+
+impl StreamedCompletion {
+    fn detail(&mut self, key: &str, value: Option<impl Into<Value>>) {
+        if let Some(value) = value {
+            self.details.insert(key.to_string(), value.into());
+        }
+    }
+
+    /// An error the provider reported in the middle of the stream.
+    fn failure(&self, message: String) -> LlmError {
+        LlmError::Provider {
+            status: self.status,
+            message,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicEvent {
+    MessageStart {
+        message: AnthropicMessage,
+    },
+    ContentBlockDelta {
+        delta: AnthropicDelta,
+    },
+    MessageDelta {
+        delta: AnthropicStop,
+        #[serde(default)]
+        usage: AnthropicUsage,
+    },
+    Error {
+        error: AnthropicError,
+    },
+    MessageStop,
+    /// Pings and block boundaries.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct AnthropicDelta {
+    /// Unset for tool input deltas.
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicStop {
+    stop_reason: Option<String>,
+    stop_sequence: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicError {
+    message: String,
+}
+
+fn parse<T: DeserializeOwned>(payload: &str) -> Result<T, LlmError> {
+    serde_json::from_str(payload).map_err(|e| LlmError::InvalidResponse {
+        message: e.to_string(),
+    })
+}
+
+fn google_chunk(chunk: GoogleResponse, streamed: &mut StreamedCompletion) -> Option<String> {
+    let text = chunk.text();
+    if let Some(usage) = &chunk.usage_metadata {
+        streamed.usage = Some(usage.token_usage());
+    }
+    // Gemini sends no terminator; its last chunk carries the finish reason.
+    if let Some(candidate) = chunk.candidates.into_iter().next() {
+        streamed.complete |= candidate.finish_reason.is_some();
+        streamed.detail("finishReason", candidate.finish_reason);
+        streamed.detail("safetyRatings", candidate.safety_ratings);
+    }
+    Some(text)
+}
+
+/// A chunk from OpenAI or LM Studio. Usage arrives in a last chunk without
+/// choices.
+fn chat_chunk(chunk: ChatCompletion, streamed: &mut StreamedCompletion) -> Option<String> {
+    streamed.detail("id", chunk.id);
+    streamed.detail("object", chunk.object);
+    if let Some(usage) = chunk.usage {
+        streamed.usage = Some(usage.token_usage());
+    }
+    let choice = chunk.choices.into_iter().next()?;
+    streamed.detail("finishReason", choice.finish_reason);
+    choice.delta?.content
+}
+
+fn anthropic_event(
+    event: AnthropicEvent,
+    streamed: &mut StreamedCompletion,
+) -> Result<Option<String>, LlmError> {
+    match event {
+        AnthropicEvent::MessageStart { message } => {
+            streamed.detail("id", message.id);
+            streamed.detail("role", message.role);
+            streamed.prompt_tokens = message.usage.input_tokens;
+            Ok(None)
+        }
+        AnthropicEvent::ContentBlockDelta { delta } => Ok(delta.text),
+        AnthropicEvent::MessageDelta { delta, usage } => {
+            streamed.detail("stopReason", delta.stop_reason);
+            streamed.detail("stopSequence", delta.stop_sequence);
+            let usage = AnthropicUsage {
+                input_tokens: streamed.prompt_tokens,
+                output_tokens: usage.output_tokens,
+            };
+            streamed.usage = Some(usage.token_usage());
+            Ok(None)
+        }
+        AnthropicEvent::Error { error } => Err(streamed.failure(error.message)),
+        AnthropicEvent::MessageStop => {
+            streamed.complete = true;
+            Ok(None)
+        }
+        AnthropicEvent::Other => Ok(None),
+    }
+}
+
+fn ollama_line(
+    line: OllamaChat,
+    streamed: &mut StreamedCompletion,
+) -> Result<Option<String>, LlmError> {
+    if let Some(error) = line.error {
+        return Err(streamed.failure(error));
+    }
+    if line.done {
+        streamed.complete = true;
+        streamed.usage = line.token_usage();
+        if let Value::Object(details) = line.details() {
+            streamed.details.extend(details);
+        }
+    }
+    Ok(line.message.map(|message| message.content))
+}
+
+impl ProviderClient {
+    /// Streams the completion of `request`, passing each piece of text to
+    /// `on_delta` as it arrives. The provider timeout bounds each wait for
+    /// data rather than the whole completion.
+    pub async fn stream(
+        &self,
+        http: &Client,
+        request: &LlmRequest,
+        on_delta: impl Fn(&str) -> Result<(), LlmError>,
+    ) -> Result<LlmResponse, LlmError> {
+        let mut response = self
+            .idle(self.open(self.request(http, request, true)?))
+            .await?;
+        let mut streamed = StreamedCompletion {
+            status: response.status().as_u16(),
+            ..StreamedCompletion::default()
+        };
+        let mut lines = LineBuffer::default();
+        while let Some(chunk) = self.idle(async { Ok(response.chunk().await?) }).await? {
+            for line in lines.push(&chunk) {
+                self.stream_line(&line, &mut streamed, &on_delta)?;
+            }
+        }
+        if let Some(line) = lines.finish() {
+            self.stream_line(&line, &mut streamed, &on_delta)?;
+        }
+        if !streamed.complete {
+            return Err(LlmError::InvalidResponse {
+                message: format!(
+                    "{} stream ended before the completion did",
+                    self.provider.as_str()
+                ),
+            });
+        }
+        Ok(self.response(
+            streamed.content,
+            streamed.usage,
+            Value::Object(streamed.details),
+        ))
+    }
+
+    /// Fails `wait` if the provider sends nothing within its timeout.
+    pub async fn idle<T>(
+        &self,
+        wait: impl Future<Output = Result<T, LlmError>>,
+    ) -> Result<T, LlmError> {
+        tokio::time::timeout(self.timeout(), wait)
+            .await
+            .unwrap_or_else(|_| {
+                Err(LlmError::Connection {
+                    message: format!(
+                        "{} sent nothing for {} ms",
+                        self.provider.as_str(),
+                        self.settings.timeout_ms
+                    ),
+                })
+            })
+    }
+
+    /// Handles one line of the body: an NDJSON object for Ollama, a
+    /// server-sent event field for everyone else.
+    fn stream_line(
+        &self,
+        line: &str,
+        streamed: &mut StreamedCompletion,
+        on_delta: &impl Fn(&str) -> Result<(), LlmError>,
+    ) -> Result<(), LlmError> {
+        let payload = if self.provider == LlmProvider::Ollama {
+            line.trim()
+        } else {
+            // Event names, ids and comments carry nothing the payload lacks.
+            match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => return Ok(()),
+            }
+        };
+        if payload.is_empty() {
+            return Ok(());
+        }
+        if payload == "[DONE]" {
+            streamed.complete = true;
+            return Ok(());
+        }
+
+        let text = match self.provider {
+            LlmProvider::GoogleGenai => google_chunk(parse(payload)?, streamed),
+            LlmProvider::Openai | LlmProvider::LmStudio => chat_chunk(parse(payload)?, streamed),
+            LlmProvider::Anthropic => anthropic_event(parse(payload)?, streamed)?,
+            LlmProvider::Ollama => ollama_line(parse(payload)?, streamed)?,
+        };
+        if let Some(text) = text.filter(|text| !text.is_empty()) {
+            streamed.content.push_str(&text);
+            on_delta(&text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Completes `request` like `generate_completion`, pushing the text to
+/// `on_event` as the provider produces it.
+#[tauri::command]
+pub async fn stream_completion(
+    request: LlmRequest,
+    on_event: Channel<StreamEvent>,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+    registry: State<'_, RequestRegistry>,
+) -> Result<LlmResponse, LlmError> {
+    let (llm, client) = llm::provider_client(&state, request.provider)?;
+    let request_id = request.id();
+    let owner = request.owner.clone();
+    let id = request_id.clone();
+    let work = async move {
+        let service = app.state::<LlmService>();
+        service
+            .stream(&llm, &client, &request, &id, |event| {
+                on_event
+                    .send(event)
+                    .map_err(|e| LlmError::from(e.to_string()))
+            })
+            .await
+    };
+    match registry.run(&request_id, owner, work).await? {
+        Some(result) => result,
+        None => Err(LlmError::Cancelled { request_id }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{client, request, run, stub_stream};
+    use std::sync::Mutex;
+
+    /// Streams from a stub sending `pieces`, returning the outcome and the
+    /// deltas passed on.
+    fn stream(
+        provider: LlmProvider,
+        pieces: Vec<&'static str>,
+    ) -> (Result<LlmResponse, LlmError>, Vec<String>) {
+        let base_url = stub_stream(pieces);
+        let client = client(provider, &base_url, Some("key"));
+        let deltas = Mutex::new(Vec::new());
+        let result = run(client.stream(&Client::new(), &request(), |text| {
+            deltas.lock().unwrap().push(text.to_string());
+            Ok(())
+        }));
+        (result, deltas.into_inner().unwrap())
+    }
+
+    fn assert_truncated(result: Result<LlmResponse, LlmError>) {
+        assert!(
+            matches!(result, Err(LlmError::InvalidResponse { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn line_buffer_holds_back_partial_lines() {
+        let mut lines = LineBuffer::default();
+        assert!(lines.push(b"data: a").is_empty());
+        assert_eq!(lines.push(b"b\r\n\r\ndata: \xc3"), ["data: ab", ""]);
+        assert_eq!(lines.push(b"\xa9\n{\"x\""), ["data: é"]);
+        assert_eq!(lines.finish().as_deref(), Some("{\"x\""));
+    }
+
+    #[test]
+    fn openai_stream_ends_with_done() {
+        let events = vec![
+            "data: {\"id\":\"c\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+            "data: {\"id\":\"c\",\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\ndata: {\"id\":\"c\",\"choices\":[{\"delta\":{\"content\":\"lo \\u00e9",
+            "\"}}]}\n\ndata: {\"id\":\"c\",\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: {\"id\":\"c\",\"choices\":[],\"usage\":{\"prompt_tokens\":1,\"completion_tokens\":2,\"total_tokens\":3}}\n\n",
+            "data: [DONE]\n\n",
+        ];
+        let (result, deltas) = stream(LlmProvider::Openai, events.clone());
+        let response = result.unwrap();
+        assert_eq!(deltas, ["Hel", "lo é"]);
+        assert_eq!(response.content, "Hello é");
+        assert_eq!(response.token_usage.unwrap().total_tokens, Some(3));
+        assert_eq!(response.metadata.unwrap()["finishReason"], "stop");
+
+        let (result, _) = stream(LlmProvider::LmStudio, events[..4].to_vec());
+        assert_truncated(result);
+    }
+
+    #[test]
+    fn anthropic_stream_ends_with_message_stop() {
+        let events = vec![
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"m\",\"role\":\"assistant\",\"content\":[],\"usage\":{\"input_tokens\":10,\"output_tokens\":1}}}\n\n",
+            "event: ping\ndata: {\"type\": \"ping\"}\n\n",
+            "event: content_block_delta\r\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\r\n\r\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":15}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        ];
+        let (result, deltas) = stream(LlmProvider::Anthropic, events.clone());
+        let response = result.unwrap();
+        assert_eq!(deltas, ["Hi"]);
+        assert_eq!(response.token_usage.unwrap().total_tokens, Some(25));
+        assert_eq!(response.metadata.unwrap()["stopReason"], "end_turn");
+
+        let (result, _) = stream(LlmProvider::Anthropic, events[..4].to_vec());
+        assert_truncated(result);
+
+        let (result, _) = stream(
+            LlmProvider::Anthropic,
+            vec!["event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n"],
+        );
+        assert!(
+            matches!(result, Err(LlmError::Provider { status: 200, ref message }) if message == "Overloaded"),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn ollama_stream_ends_with_done_line() {
+        let lines = vec![
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"A\"},\"done\":false}\n{\"message\":{\"role\":\"assistant\",\"content\":\"B\"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"total_duration\":5,\"prompt_eval_count\":2,\"eval_count\":3}",
+        ];
+        let (result, deltas) = stream(LlmProvider::Ollama, lines.clone());
+        assert_eq!(deltas, ["A", "B"]);
+        assert_eq!(result.unwrap().token_usage.unwrap().total_tokens, Some(5));
+
+        let (result, _) = stream(LlmProvider::Ollama, lines[..1].to_vec());
+        assert_truncated(result);
+
+        let (result, _) = stream(
+            LlmProvider::Ollama,
+            vec!["{\"error\":\"model not found\"}\n"],
+        );
+        assert!(
+            matches!(result, Err(LlmError::Provider { ref message, .. }) if message == "model not found"),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn google_stream_ends_with_finish_reason() {
+        let chunks = vec![
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"G1\"}],\"role\":\"model\"}}]}\r\n\r\n",
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"G2\"}]},\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":1,\"candidatesTokenCount\":2,\"totalTokenCount\":3}}\r\n\r\n",
+        ];
+        let (result, deltas) = stream(LlmProvider::GoogleGenai, chunks.clone());
+        assert_eq!(deltas, ["G1", "G2"]);
+        assert_eq!(result.unwrap().token_usage.unwrap().total_tokens, Some(3));
+
+        let (result, _) = stream(LlmProvider::GoogleGenai, chunks[..1].to_vec());
+        assert_truncated(result);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::tasks::{Task, TaskStatus};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+
+/// Dependency graph over the stored tasks. Edges point from a dependency to
+/// the tasks that wait on it.
+pub struct TaskGraph<'a> {
+    tasks: &'a HashMap<String, Task>,
+}
+
+impl<'a> TaskGraph<'a> {
+    pub fn new(tasks: &'a HashMap<String, Task>) -> Self {
+        TaskGraph { tasks }
+    }
+
+    /// Checks that inserting (or replacing) `task` keeps the graph a DAG whose
+    /// edges all point at known tasks.
+    pub fn check_insert(&self, task: &Task) -> Result<(), String> {
+        if let Some(missing) = task
+            .dependencies
+            .iter()
+            .find(|dependency| !self.tasks.contains_key(*dependency))
+        {
+            return Err(format!(
+                "task {} depends on unknown task {}",
+                task.id, missing
+            ));
+        }
+
+        // Only a path from one of the new dependencies back to the task itself
+        // can close a cycle, since the rest of the graph is already acyclic.
+        let mut stack: Vec<&str> = task.dependencies.iter().map(String::as_str).collect();
+        let mut visited = BTreeSet::new();
+        while let Some(current) = stack.pop() {
+            if current == task.id {
+                return Err(format!(
+                    "task {} would introduce a dependency cycle",
+                    task.id
+                ));
+            }
+            if !visited.insert(current) {
+                continue;
+            }
+            if let Some(node) = self.tasks.get(current) {
+                stack.extend(node.dependencies.iter().map(String::as_str));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that no remaining task still depends on `task_id`.
+    pub fn check_remove(&self, task_id: &str) -> Result<(), String> {
+        let mut dependents: Vec<&str> = self
+            .tasks
+            .values()
+            .filter(|task| task.dependencies.iter().any(|dep| dep == task_id))
+            .map(|task| task.id.as_str())
+            .collect();
+        if dependents.is_empty() {
+            return Ok(());
+        }
+
+        dependents.sort_unstable();
+        Err(format!(
+            "task {} is still required by: {}",
+            task_id,
+            dependents.join(", ")
+        ))
+    }
+
+    /// Returns whether every dependency of `task` has completed.
+    pub fn dependencies_met(&self, task: &Task) -> bool {
+        task.dependencies.iter().all(|dependency| {
+            self.tasks
+                .get(dependency)
+                .is_some_and(|dep| dep.status == TaskStatus::Completed)
+        })
+    }
+
+    /// Pending tasks whose dependencies have all completed, in display order.
+    pub fn ready(&self) -> Vec<&'a Task> {
+        let mut ready: Vec<&Task> = self
+            .tasks
+            .values()
+            .filter(|task| task.status == TaskStatus::Pending && self.dependencies_met(task))
+            .collect();
+        ready.sort_by(|a, b| display_key(a).cmp(&display_key(b)));
+        ready
+    }
+
+    /// Topological ordering of all tasks. Among tasks whose dependencies are
+    /// already placed, higher priority and older tasks come first.
+    pub fn topological_order(&self) -> Result<Vec<&'a Task>, String> {
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        let mut dependents: HashMap<&str, Vec<&Task>> = HashMap::new();
+        for task in self.tasks.values() {
+            remaining.insert(&task.id, task.dependencies.len());
+            for dependency in &task.dependencies {
+                dependents.entry(dependency).or_default().push(task);
+            }
+        }
+
+        let mut frontier: BTreeSet<_> = self
+            .tasks
+            .values()
+            .filter(|task| task.dependencies.is_empty())
+            .map(display_key)
+            .collect();
+
+        let mut order = Vec::with_capacity(self.tasks.len());
+        while let Some((_, _, id)) = frontier.pop_first() {
+            order.push(&self.tasks[id]);
+            for dependent in dependents.get(id).into_iter().flatten() {
+                let count = remaining
+                    .get_mut(dependent.id.as_str())
+                    .expect("every task has a dependency count");
+                *count -= 1;
+                if *count == 0 {
+                    frontier.insert(display_key(dependent));
+                }
+            }
+        }
+
+        if order.len() != self.tasks.len() {
+            return Err("stored tasks contain a dependency cycle".to_string());
+        }
+        Ok(order)
+    }
+}
+
+fn display_key(task: &Task) -> (Reverse<u8>, i64, &str) {
+    (Reverse(task.priority.into()), task.created_at, &task.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::TaskPriority;
+    use crate::test_support::task;
+
+    fn graph(tasks: Vec<Task>) -> HashMap<String, Task> {
+        tasks
+            .into_iter()
+            .map(|task| (task.id.clone(), task))
+            .collect()
+    }
+
+    fn ids(tasks: Vec<&Task>) -> Vec<&str> {
+        tasks.into_iter().map(|task| task.id.as_str()).collect()
+    }
+
+    #[test]
+    fn inserts_must_keep_a_dag_of_known_tasks() {
+        let tasks = graph(vec![task("a", &[]), task("b", &["a"]), task("c", &["b"])]);
+        let graph = TaskGraph::new(&tasks);
+
+        let error = graph.check_insert(&task("d", &["a", "x"])).unwrap_err();
+        assert_eq!(error, "task d depends on unknown task x");
+        let error = graph.check_insert(&task("a", &["c"])).unwrap_err();
+        assert_eq!(error, "task a would introduce a dependency cycle");
+        graph.check_insert(&task("d", &["a", "c"])).unwrap();
+        graph.check_insert(&task("b", &[])).unwrap();
+
+        let error = graph.check_remove("a").unwrap_err();
+        assert_eq!(error, "task a is still required by: b");
+        graph.check_remove("c").unwrap();
+    }
+
+    #[test]
+    fn order_and_ready_set_follow_dependencies_then_priority() {
+        let mut low = task("low", &[]);
+        low.priority = TaskPriority::Low;
+        let mut high = task("high", &[]);
+        high.priority = TaskPriority::High;
+        high.created_at = 5;
+        let mut older = task("older", &[]);
+        older.priority = TaskPriority::High;
+        let mut tasks = graph(vec![
+            task("join", &["low", "high"]),
+            task("last", &["join"]),
+            low,
+            high,
+            older,
+        ]);
+
+        let order = TaskGraph::new(&tasks).topological_order().unwrap();
+        assert_eq!(ids(order), ["older", "high", "low", "join", "last"]);
+        let ready = TaskGraph::new(&tasks).ready();
+        assert_eq!(ids(ready), ["older", "high", "low"]);
+
+        for id in ["low", "high", "older"] {
+            tasks.get_mut(id).unwrap().status = TaskStatus::Completed;
+        }
+        assert_eq!(ids(TaskGraph::new(&tasks).ready()), ["join"]);
+
+        tasks.get_mut("low").unwrap().dependencies = vec!["last".to_string()];
+        assert!(TaskGraph::new(&tasks).topological_order().is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::journal::Mutation;
+use crate::lifecycle::TaskTransition;
+use crate::store::{json_column, now_millis, to_json, Store};
+use crate::task_graph::TaskGraph;
+use crate::AppStateType;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, OptionalExtension, Row, ToSql};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use tauri::State;
+
+/// Task priority levels used by the orchestrator, serialized as the numeric
+/// values of the frontend `TaskPriority` enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum TaskPriority {
+    Low = 1,
+    Medium = 2,
+    High = 3,
+    Critical = 4,
+}
+
+impl TryFrom<u8> for TaskPriority {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(TaskPriority::Low),
+            2 => Ok(TaskPriority::Medium),
+            3 => Ok(TaskPriority::High),
+            4 => Ok(TaskPriority::Critical),
+            other => Err(format!("invalid task priority: {other}")),
+        }
+    }
+}
+
+impl From<TaskPriority> for u8 {
+    fn from(priority: TaskPriority) -> Self {
+        priority as u8
+    }
+}
+
+impl ToSql for TaskPriority {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(u8::from(*self)))
+    }
+}
+
+impl FromSql for TaskPriority {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = u8::column_result(value)?;
+        TaskPriority::try_from(raw).map_err(|e| FromSqlError::Other(e.into()))
+    }
+}
+
+/// Lifecycle status of a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Pending,
+    Assigned,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl TaskStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskStatus::Pending => "pending",
+            TaskStatus::Assigned => "assigned",
+            TaskStatus::Running => "running",
+            TaskStatus::Completed => "completed",
+            TaskStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(TaskStatus::Pending),
+            "assigned" => Some(TaskStatus::Assigned),
+            "running" => Some(TaskStatus::Running),
+            "completed" => Some(TaskStatus::Completed),
+            "failed" => Some(TaskStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql for TaskStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for TaskStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        TaskStatus::parse(raw)
+            .ok_or_else(|| FromSqlError::Other(format!("invalid task status: {raw}").into()))
+    }
+}
+
+/// Task definition mirroring the frontend `TaskSchema`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub task_type: String,
+    pub priority: TaskPriority,
+    pub dependencies: Vec<String>,
+    pub payload: Map<String, Value>,
+    pub created_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assigned_agent_id: Option<String>,
+    pub status: TaskStatus,
+}
+
+impl Task {
+    /// Checks the invariants that the schema alone cannot express.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("task id must not be empty".to_string());
+        }
+        if self.task_type.trim().is_empty() {
+            return Err(format!("task {} has an empty type", self.id));
+        }
+        if self.created_at < 0 {
+            return Err(format!("task {} has a negative createdAt", self.id));
+        }
+
+        let mut seen = HashSet::new();
+        for dependency in &self.dependencies {
+            if dependency == &self.id {
+                return Err(format!("task {} depends on itself", self.id));
+            }
+            if !seen.insert(dependency) {
+                return Err(format!(
+                    "task {} lists dependency {} more than once",
+                    self.id, dependency
+                ));
+            }
+        }
+
+        if let Some(agent_id) = &self.assigned_agent_id {
+            if agent_id.trim().is_empty() {
+                return Err(format!("task {} has an empty assignedAgentId", self.id));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Task {
+            id: row.get("id")?,
+            task_type: row.get("type")?,
+            priority: row.get("priority")?,
+            dependencies: json_column(row, "dependencies")?,
+            payload: json_column(row, "payload")?,
+            created_at: row.get("created_at")?,
+            assigned_agent_id: row.get("assigned_agent_id")?,
+            status: row.get("status")?,
+        })
+    }
+}
+
+impl Store {
+    /// Tasks that have not been removed.
+    pub fn tasks(&self) -> rusqlite::Result<HashMap<String, Task>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM tasks WHERE removed_at IS NULL")?;
+        let rows = stmt.query_map([], Task::from_row)?;
+        rows.map(|task| task.map(|task| (task.id.clone(), task)))
+            .collect()
+    }
+
+    pub fn task(&self, task_id: &str) -> rusqlite::Result<Option<Task>> {
+        self.conn
+            .query_row(
+                "SELECT * FROM tasks WHERE id = ?1 AND removed_at IS NULL",
+                params![task_id],
+                Task::from_row,
+            )
+            .optional()
+    }
+
+    pub fn insert_task(&self, task: &Task) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO tasks
+                (id, type, priority, dependencies, payload, created_at, assigned_agent_id, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(id) DO UPDATE SET
+                type = excluded.type,
+                priority = excluded.priority,
+                dependencies = excluded.dependencies,
+                payload = excluded.payload,
+                created_at = excluded.created_at,
+                assigned_agent_id = excluded.assigned_agent_id,
+                status = excluded.status",
+            params![
+                task.id,
+                task.task_type,
+                task.priority,
+                to_json(&task.dependencies)?,
+                to_json(&task.payload)?,
+                task.created_at,
+                task.assigned_agent_id,
+                task.status,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[tauri::command]
+pub fn get_task_list(state: State<AppStateType>) -> Result<HashMap<String, Task>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.store.tasks().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_ready_tasks(state: State<AppStateType>) -> Result<Vec<Task>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let tasks = app_state.store.tasks().map_err(|e| e.to_string())?;
+    Ok(TaskGraph::new(&tasks)
+        .ready()
+        .into_iter()
+        .cloned()
+        .collect())
+}
+
+#[tauri::command]
+pub fn get_task_order(state: State<AppStateType>) -> Result<Vec<Task>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    let tasks = app_state.store.tasks().map_err(|e| e.to_string())?;
+    let order = TaskGraph::new(&tasks).topological_order()?;
+    Ok(order.into_iter().cloned().collect())
+}
+
+#[tauri::command]
+pub fn add_task(task: Task, state: State<AppStateType>) -> Result<(), String> {
+    task.validate()?;
+    if task.status != TaskStatus::Pending {
+        return Err(format!(
+            "task {} must be created as pending, not {}",
+            task.id, task.status
+        ));
+    }
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let tasks = app_state.store.tasks().map_err(|e| e.to_string())?;
+    if tasks.contains_key(&task.id) {
+        return Err(format!("task {} already exists", task.id));
+    }
+    if app_state
+        .store
+        .removed_task(&task.id)
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        return Err(format!(
+            "task {} was removed; undo the removal or wait for it to be purged",
+            task.id
+        ));
+    }
+    TaskGraph::new(&tasks).check_insert(&task)?;
+
+    let created = TaskTransition {
+        task_id: task.id.clone(),
+        from: None,
+        to: task.status,
+        agent_id: task.assigned_agent_id.clone(),
+        reason: None,
+        timestamp: task.created_at,
+    };
+    app_state.commit(Mutation::SaveTransition {
+        task,
+        transition: created,
+    })
+}
+
+/// Removes a task by turning it into a tombstone that `undo_remove_task` can
+/// bring back until it is purged.
+#[tauri::command]
+pub fn remove_task(
+    task_id: String,
+    removed_by: Option<String>,
+    state: State<AppStateType>,
+) -> Result<(), String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let tasks = app_state.store.tasks().map_err(|e| e.to_string())?;
+    if !tasks.contains_key(&task_id) {
+        return Err(format!("task {task_id} does not exist"));
+    }
+    TaskGraph::new(&tasks).check_remove(&task_id)?;
+
+    app_state.commit(Mutation::RemoveTask {
+        task_id,
+        removed_at: now_millis(),
+        removed_by,
+    })
+}
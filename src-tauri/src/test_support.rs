@@ -0,0 +1,171 @@
+//! Fixtures shared by the tests of several modules: an installation in a
+//! temporary directory and local stand-ins for LLM providers.
+
+use crate::journal::Mutation;
+use crate::lifecycle::TaskTransition;
+use crate::llm::{LlmRequest, ProviderClient};
+use crate::model_catalog::ModelCatalog;
+use crate::settings::{LlmProvider, ProviderSettings, SettingsFile};
+use crate::storage::QuotaStatus;
+use crate::tasks::{Task, TaskPriority, TaskStatus};
+use crate::vault::Vault;
+use crate::workspaces::{self, WorkspaceRegistry};
+use crate::AppState;
+use std::future::Future;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+/// An empty directory of its own under the system temp directory, which the
+/// test removes when done.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("esaf-{name}-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The state of an installation under `root`, as `main` builds it, with its
+/// default workspace open.
+pub fn app_state(root: &Path) -> AppState {
+    let workspaces = WorkspaceRegistry::load(root).unwrap();
+    let (store, journal) = workspaces::open_workspace(&workspaces.active_dir()).unwrap();
+    AppState {
+        store,
+        journal,
+        workspaces,
+        vault: Vault::load(root).unwrap(),
+        settings_file: SettingsFile::load(root).unwrap(),
+        catalog: ModelCatalog::load(root),
+        quota: QuotaStatus::default(),
+    }
+}
+
+/// A pending MEDIUM `data_analysis` task created at 0 that waits on
+/// `dependencies`.
+pub fn task(id: &str, dependencies: &[&str]) -> Task {
+    Task {
+        id: id.to_string(),
+        task_type: "data_analysis".to_string(),
+        priority: TaskPriority::Medium,
+        dependencies: dependencies.iter().map(|id| id.to_string()).collect(),
+        payload: Default::default(),
+        created_at: 0,
+        assigned_agent_id: None,
+        status: TaskStatus::Pending,
+    }
+}
+
+/// Stores `task` with its creation transition, as `add_task` does.
+pub fn add_task(state: &mut AppState, task: Task) {
+    let transition = TaskTransition {
+        task_id: task.id.clone(),
+        from: None,
+        to: task.status,
+        agent_id: None,
+        reason: None,
+        timestamp: task.created_at,
+    };
+    state
+        .commit(Mutation::SaveTransition { task, transition })
+        .unwrap();
+}
+
+/// Reads one HTTP request from `stream`, returning its head and body.
+fn read_request(stream: &TcpStream) -> String {
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+        request.push_str(&line);
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    request + &String::from_utf8(body).unwrap()
+}
+
+/// Serves one `(status, body)` JSON response per connection, in order, and
+/// reports each raw request it answered.
+pub fn stub(responses: Vec<(u16, &str)>) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let responses: Vec<_> = responses
+        .into_iter()
+        .map(|(status, body)| (status, body.to_string()))
+        .collect();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (status, body) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_request(&stream);
+            write!(
+                stream,
+                "HTTP/1.1 {status} Stub\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            let _ = sender.send(request);
+        }
+    });
+    (format!("http://{address}"), receiver)
+}
+
+/// Answers one request with a body sent in `pieces`, flushed apart, as a
+/// streaming provider does. The body ends when the connection closes.
+pub fn stub_stream(pieces: Vec<&'static str>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        read_request(&stream);
+        write!(stream, "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n").unwrap();
+        for piece in pieces {
+            stream.write_all(piece.as_bytes()).unwrap();
+            stream.flush().unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+    format!("http://{address}")
+}
+
+/// A client of `provider` at `base_url` using model `m1`.
+pub fn client(provider: LlmProvider, base_url: &str, api_key: Option<&str>) -> ProviderClient {
+    ProviderClient {
+        provider,
+        settings: ProviderSettings {
+            base_url: base_url.to_string(),
+            model: "m1".to_string(),
+            temperature: 0.5,
+            max_tokens: 10,
+            timeout_ms: 5000,
+        },
+        api_key: api_key.map(|key| Zeroizing::new(key.to_string())),
+    }
+}
+
+/// A request with a system prompt and its own token limit.
+pub fn request() -> LlmRequest {
+    serde_json::from_value(serde_json::json!({
+        "prompt": "hi data",
+        "systemPrompt": "sys",
+        "maxTokens": 7,
+    }))
+    .unwrap()
+}
+
+pub fn run<F: Future>(future: F) -> F::Output {
+    tauri::async_runtime::block_on(future)
+}
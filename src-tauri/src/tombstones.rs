@@ -0,0 +1,162 @@
+use crate::journal::Mutation;
+use crate::store::{now_millis, Store};
+use crate::task_graph::TaskGraph;
+use crate::tasks::Task;
+use crate::{AppState, AppStateType};
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+/// How long removed tasks are kept before being purged, unless the
+/// `taskRetentionMs` setting says otherwise.
+pub const DEFAULT_TASK_RETENTION_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+/// How often the background purge looks for expired tombstones.
+const PURGE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A removed task together with who removed it and when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemovedTask {
+    #[serde(flatten)]
+    pub task: Task,
+    pub removed_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub removed_by: Option<String>,
+}
+
+impl RemovedTask {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(RemovedTask {
+            task: Task::from_row(row)?,
+            removed_at: row.get("removed_at")?,
+            removed_by: row.get("removed_by")?,
+        })
+    }
+}
+
+impl Store {
+    /// Removed tasks that have not been purged yet, most recently removed
+    /// first.
+    pub fn removed_tasks(&self) -> rusqlite::Result<Vec<RemovedTask>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM tasks WHERE removed_at IS NOT NULL ORDER BY removed_at DESC, id",
+        )?;
+        let rows = stmt.query_map([], RemovedTask::from_row)?;
+        rows.collect()
+    }
+
+    pub fn removed_task(&self, task_id: &str) -> rusqlite::Result<Option<RemovedTask>> {
+        self.conn
+            .query_row(
+                "SELECT * FROM tasks WHERE id = ?1 AND removed_at IS NOT NULL",
+                params![task_id],
+                RemovedTask::from_row,
+            )
+            .optional()
+    }
+
+    pub fn tombstone_task(
+        &self,
+        task_id: &str,
+        removed_at: i64,
+        removed_by: Option<&str>,
+    ) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE tasks SET removed_at = ?2, removed_by = ?3 WHERE id = ?1",
+            params![task_id, removed_at, removed_by],
+        )?;
+        Ok(())
+    }
+
+    pub fn undo_task_removal(&self, task_id: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE tasks SET removed_at = NULL, removed_by = NULL WHERE id = ?1",
+            params![task_id],
+        )?;
+        Ok(())
+    }
+
+    /// Physically deletes the given tombstones along with their history.
+    pub fn purge_tasks(&self, task_ids: &[String]) -> rusqlite::Result<()> {
+        for task_id in task_ids {
+            self.conn.execute(
+                "DELETE FROM tasks WHERE id = ?1 AND removed_at IS NOT NULL",
+                params![task_id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Ids of tombstones removed at or before `cutoff`.
+    pub fn expired_tombstones(&self, cutoff: i64) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM tasks WHERE removed_at <= ?1 ORDER BY id")?;
+        let rows = stmt.query_map(params![cutoff], |row| row.get(0))?;
+        rows.collect()
+    }
+}
+
+/// Purges tombstones older than the retention period, returning their ids.
+pub fn purge_expired_tasks(state: &mut AppState, now: i64) -> Result<Vec<String>, String> {
+    // Retention period for tombstones, from the `taskRetentionMs` setting.
+    let retention = state
+        .settings()
+        .map_err(|e| e.to_string())?
+        .settings
+        .task_retention_ms;
+    let task_ids = state
+        .store
+        .expired_tombstones(now.saturating_sub(retention))
+        .map_err(|e| e.to_string())?;
+    if !task_ids.is_empty() {
+        state.commit(Mutation::PurgeTasks {
+            task_ids: task_ids.clone(),
+        })?;
+    }
+    Ok(task_ids)
+}
+
+/// Periodically purges expired tombstones for as long as the app runs.
+pub fn schedule_purge(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let state = app.state::<AppStateType>();
+            let Ok(mut app_state) = state.lock() else {
+                break;
+            };
+            if let Err(e) = purge_expired_tasks(&mut app_state, now_millis()) {
+                log::error!("failed to purge removed tasks: {e}");
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn list_removed_tasks(state: State<AppStateType>) -> Result<Vec<RemovedTask>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.store.removed_tasks().map_err(|e| e.to_string())
+}
+
+/// Brings a removed task back, provided the tasks it depends on are still
+/// present.
+#[tauri::command]
+pub fn undo_remove_task(task_id: String, state: State<AppStateType>) -> Result<Task, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let removed = app_state
+        .store
+        .removed_task(&task_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("task {task_id} is not in the removed tasks"))?;
+
+    let tasks = app_state.store.tasks().map_err(|e| e.to_string())?;
+    TaskGraph::new(&tasks).check_insert(&removed.task)?;
+
+    app_state.commit(Mutation::UndoRemoveTask { task_id })?;
+    Ok(removed.task)
+}
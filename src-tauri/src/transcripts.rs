@@ -0,0 +1,878 @@
+use crate::chats::{
+    title_from_message, ChatMessage, ChatMessageType, ChatSession, DEFAULT_CHAT_TITLE,
+};
+use crate::journal::Mutation;
+use crate::search::escape_html;
+use crate::store::{now_millis, Store};
+use crate::AppStateType;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+use uuid::Uuid;
+
+/// Name given to assistant messages of imports that do not name a model.
+const IMPORTED_ASSISTANT_NAME: &str = "Assistant";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    Markdown,
+    Jsonl,
+    Html,
+}
+
+impl TranscriptFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Markdown => "md",
+            TranscriptFormat::Jsonl => "jsonl",
+            TranscriptFormat::Html => "html",
+        }
+    }
+
+    fn filter_name(self) -> &'static str {
+        match self {
+            TranscriptFormat::Markdown => "Markdown transcript",
+            TranscriptFormat::Jsonl => "JSON Lines transcript",
+            TranscriptFormat::Html => "HTML transcript",
+        }
+    }
+}
+
+/// Formats `import_chat_sessions` reads. `Openai` covers both ChatGPT data
+/// exports (`conversations.json`) and chat completion transcripts, i.e.
+/// objects with a `messages` array of `{role, content}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Jsonl,
+    Openai,
+}
+
+impl ImportFormat {
+    /// Tells a JSON document of conversations from JSON Lines.
+    fn detect(text: &str) -> Self {
+        match serde_json::from_str::<Value>(text) {
+            Ok(Value::Array(_)) => ImportFormat::Openai,
+            Ok(Value::Object(object))
+                if object.contains_key("mapping") || object.contains_key("messages") =>
+            {
+                ImportFormat::Openai
+            }
+            _ => ImportFormat::Jsonl,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+    /// Keep the agents' thinking messages, which are left out of
+    /// transcripts by default.
+    pub include_thinking: bool,
+}
+
+/// A session with its messages in append order.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub session: ChatSession,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// One line of a JSON Lines transcript: a message with the title of its
+/// session, so the file stays readable line by line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranscriptLine {
+    #[serde(flatten)]
+    message: ChatMessage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_title: Option<String>,
+}
+
+/// Formats `ms` since the epoch as `YYYY-MM-DD HH:MM UTC`.
+fn format_timestamp(ms: i64) -> String {
+    let secs = ms.div_euclid(1000);
+    let (days, time) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+/// Who a message is shown as coming from.
+fn speaker(message: &ChatMessage) -> String {
+    if let Some(agent_name) = &message.agent_name {
+        return agent_name.clone();
+    }
+    match message.message_type {
+        ChatMessageType::User => "User",
+        ChatMessageType::Agent => "Agent",
+        ChatMessageType::System => "System",
+        ChatMessageType::Conversation => "Conversation",
+        ChatMessageType::Thinking => "Thinking",
+    }
+    .to_string()
+}
+
+fn visible<'a>(
+    transcript: &'a Transcript,
+    options: &'a ExportOptions,
+) -> impl Iterator<Item = &'a ChatMessage> {
+    transcript.messages.iter().filter(|message| {
+        options.include_thinking || message.message_type != ChatMessageType::Thinking
+    })
+}
+
+pub fn render_markdown(transcripts: &[Transcript], options: &ExportOptions) -> String {
+    let mut out = String::new();
+    for (i, transcript) in transcripts.iter().enumerate() {
+        if i > 0 {
+            out.push_str("\n---\n\n");
+        }
+        let session = &transcript.session;
+        out.push_str(&format!("# {}\n\n", session.title));
+        out.push_str(&format!(
+            "_{} to {}_\n\n",
+            format_timestamp(session.created),
+            format_timestamp(session.last_activity)
+        ));
+        if !session.tags.is_empty() {
+            out.push_str(&format!("Tags: {}\n\n", session.tags.join(", ")));
+        }
+        if let Some(summary) = &session.summary {
+            out.push_str(&format!("> {}\n\n", summary.replace('\n', "\n> ")));
+        }
+
+        for message in visible(transcript, options) {
+            out.push_str(&format!(
+                "### {} · {}\n\n",
+                speaker(message),
+                format_timestamp(message.timestamp)
+            ));
+            let content = message.content.trim_end();
+            if message.message_type == ChatMessageType::Thinking {
+                out.push_str(&format!("> {}\n\n", content.replace('\n', "\n> ")));
+            } else {
+                out.push_str(content);
+                out.push_str("\n\n");
+            }
+        }
+    }
+    out
+}
+
+pub fn render_jsonl(transcripts: &[Transcript], options: &ExportOptions) -> Result<String, String> {
+    let mut out = String::new();
+    for transcript in transcripts {
+        for message in visible(transcript, options) {
+            let line = TranscriptLine {
+                message: message.clone(),
+                session_title: Some(transcript.session.title.clone()),
+            };
+            out.push_str(&serde_json::to_string(&line).map_err(|e| e.to_string())?);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+const HTML_STYLE: &str = "
+body { font-family: system-ui, sans-serif; max-width: 50rem; margin: 2rem auto; padding: 0 1rem; color: #1f2937; }
+section + section { border-top: 1px solid #d1d5db; margin-top: 2rem; padding-top: 1rem; }
+.meta { color: #6b7280; font-size: 0.875rem; }
+.message { border-radius: 0.5rem; margin: 1rem 0; padding: 0.75rem 1rem; background: #f3f4f6; }
+.message.user { background: #dbeafe; }
+.message.system { background: #fef3c7; }
+.message.thinking { background: #f5f3ff; font-style: italic; }
+.message header { font-weight: 600; margin-bottom: 0.25rem; }
+.message header time { color: #6b7280; font-weight: normal; margin-left: 0.5rem; }
+.content { white-space: pre-wrap; overflow-wrap: anywhere; }
+";
+
+/// A single page with inline styles and no scripts or external resources.
+pub fn render_html(transcripts: &[Transcript], options: &ExportOptions) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>");
+    let title = match transcripts {
+        [transcript] => transcript.session.title.as_str(),
+        _ => "ESAF chat transcripts",
+    };
+    escape_html(title, &mut out);
+    out.push_str("</title>\n<style>");
+    out.push_str(HTML_STYLE);
+    out.push_str("</style>\n</head>\n<body>\n");
+
+    for transcript in transcripts {
+        let session = &transcript.session;
+        out.push_str("<section>\n<h1>");
+        escape_html(&session.title, &mut out);
+        out.push_str("</h1>\n<p class=\"meta\">");
+        escape_html(
+            &format!(
+                "{} to {}",
+                format_timestamp(session.created),
+                format_timestamp(session.last_activity)
+            ),
+            &mut out,
+        );
+        if !session.tags.is_empty() {
+            out.push_str(" · ");
+            escape_html(&session.tags.join(", "), &mut out);
+        }
+        out.push_str("</p>\n");
+
+        for message in visible(transcript, options) {
+            out.push_str(&format!(
+                "<article class=\"message {}\">\n<header>",
+                message.message_type.as_str()
+            ));
+            escape_html(&speaker(message), &mut out);
+            out.push_str("<time>");
+            escape_html(&format_timestamp(message.timestamp), &mut out);
+            out.push_str("</time></header>\n<div class=\"content\">");
+            escape_html(message.content.trim_end(), &mut out);
+            out.push_str("</div>\n</article>\n");
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Turns imported messages into a new session. Sessions and messages get
+/// fresh ids, so importing the same file twice never collides.
+fn imported_transcript(
+    title: Option<String>,
+    mut messages: Vec<ChatMessage>,
+) -> Option<Transcript> {
+    if messages.is_empty() {
+        return None;
+    }
+    let title = title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .or_else(|| {
+            messages
+                .iter()
+                .find(|message| message.message_type == ChatMessageType::User)
+                .map(|message| title_from_message(&message.content))
+        })
+        .unwrap_or_else(|| DEFAULT_CHAT_TITLE.to_string());
+
+    let mut session = ChatSession::new(title);
+    session.created = messages
+        .iter()
+        .map(|m| m.timestamp)
+        .min()
+        .unwrap_or(session.created);
+    session.last_activity = messages
+        .iter()
+        .map(|m| m.timestamp)
+        .max()
+        .unwrap_or(session.last_activity);
+    session.message_count = messages.len() as u32;
+    for message in &mut messages {
+        message.id = Uuid::new_v4().to_string();
+        message.session_id = session.id.clone();
+    }
+    Some(Transcript { session, messages })
+}
+
+/// Reads a JSON Lines transcript, one session per distinct `sessionId`.
+pub fn parse_jsonl(text: &str) -> Result<Vec<Transcript>, String> {
+    let mut order: Vec<String> = Vec::new();
+    let mut sessions: HashMap<String, (Option<String>, Vec<ChatMessage>)> = HashMap::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line: TranscriptLine =
+            serde_json::from_str(line).map_err(|e| format!("line {}: {e}", n + 1))?;
+        let session_id = line.message.session_id.clone();
+        let entry = sessions.entry(session_id.clone()).or_insert_with(|| {
+            order.push(session_id);
+            (None, Vec::new())
+        });
+        if entry.0.is_none() {
+            entry.0 = line.session_title;
+        }
+        entry.1.push(line.message);
+    }
+
+    Ok(order
+        .into_iter()
+        .filter_map(|session_id| {
+            let (title, messages) = sessions.remove(&session_id)?;
+            imported_transcript(title, messages)
+        })
+        .collect())
+}
+
+/// Plain text of an OpenAI message `content`: a string, a ChatGPT
+/// `{parts: [...]}` object, or an array of `{type: "text", text}` parts.
+/// Non-text parts such as images are skipped.
+fn openai_text(content: &Value) -> String {
+    let parts = match content {
+        Value::String(text) => return text.clone(),
+        Value::Object(object) => object.get("parts").and_then(Value::as_array),
+        Value::Array(parts) => Some(parts),
+        _ => None,
+    };
+    parts
+        .into_iter()
+        .flatten()
+        .filter_map(|part| match part {
+            Value::String(text) => Some(text.as_str()),
+            Value::Object(part) => part.get("text").and_then(Value::as_str),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Converts one OpenAI message. `fallback_time` is used for messages
+/// without their own time, so the order is kept.
+fn openai_message(message: &Value, fallback_time: i64) -> Option<ChatMessage> {
+    let role = message
+        .pointer("/author/role")
+        .or_else(|| message.get("role"))
+        .and_then(Value::as_str)?;
+    let content = openai_text(message.get("content")?);
+    if content.trim().is_empty() {
+        return None;
+    }
+
+    let (message_type, agent_name) = match role {
+        "user" => (ChatMessageType::User, None),
+        "system" | "developer" => (ChatMessageType::System, None),
+        "tool" => (
+            ChatMessageType::Agent,
+            Some(
+                message
+                    .pointer("/author/name")
+                    .or_else(|| message.get("name"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("Tool")
+                    .to_string(),
+            ),
+        ),
+        _ => (
+            ChatMessageType::Agent,
+            Some(
+                message
+                    .pointer("/metadata/model_slug")
+                    .and_then(Value::as_str)
+                    .unwrap_or(IMPORTED_ASSISTANT_NAME)
+                    .to_string(),
+            ),
+        ),
+    };
+    let timestamp = message
+        .get("create_time")
+        .and_then(Value::as_f64)
+        .map_or(fallback_time, |secs| (secs * 1000.0) as i64);
+
+    Some(ChatMessage {
+        id: String::new(),
+        message_type,
+        content,
+        timestamp,
+        agent_name,
+        metadata: None,
+        session_id: String::new(),
+    })
+}
+
+/// Messages of a ChatGPT export conversation along its current branch,
+/// oldest first.
+fn chatgpt_branch(conversation: &Value) -> Vec<&Value> {
+    let Some(mapping) = conversation.get("mapping").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    let leaf = conversation
+        .get("current_node")
+        .and_then(Value::as_str)
+        .or_else(|| {
+            // Without a current node, follow the last childless node.
+            mapping
+                .iter()
+                .rev()
+                .find(|(_, node)| {
+                    node.get("children")
+                        .and_then(Value::as_array)
+                        .is_none_or(|children| children.is_empty())
+                })
+                .map(|(id, _)| id.as_str())
+        });
+
+    let mut branch = Vec::new();
+    let mut next = leaf;
+    while let Some(node) = next.and_then(|id| mapping.get(id)) {
+        if branch.len() > mapping.len() {
+            break; // A cycle; the export is damaged.
+        }
+        if let Some(message) = node.get("message").filter(|message| !message.is_null()) {
+            branch.push(message);
+        }
+        next = node.get("parent").and_then(Value::as_str);
+    }
+    branch.reverse();
+    branch
+}
+
+/// Reads a ChatGPT data export or chat completion transcripts, one session
+/// per conversation.
+pub fn parse_openai(text: &str) -> Result<Vec<Transcript>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let conversations = match value {
+        Value::Array(conversations) => conversations,
+        conversation => vec![conversation],
+    };
+
+    let now = now_millis();
+    let mut transcripts = Vec::new();
+    for (i, conversation) in conversations.iter().enumerate() {
+        let raw: Vec<&Value> = if conversation.get("mapping").is_some() {
+            chatgpt_branch(conversation)
+        } else if let Some(messages) = conversation.get("messages").and_then(Value::as_array) {
+            messages.iter().collect()
+        } else {
+            return Err(format!(
+                "conversation {} has neither a mapping nor messages",
+                i + 1
+            ));
+        };
+
+        let mut time = conversation
+            .get("create_time")
+            .and_then(Value::as_f64)
+            .map_or(now, |secs| (secs * 1000.0) as i64);
+        let mut messages = Vec::new();
+        for message in raw {
+            if let Some(message) = openai_message(message, time) {
+                time = message.timestamp;
+                messages.push(message);
+            }
+        }
+        let title = conversation
+            .get("title")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        transcripts.extend(imported_transcript(title, messages));
+    }
+    Ok(transcripts)
+}
+
+impl Store {
+    pub fn import_chat_sessions(
+        &self,
+        sessions: &[ChatSession],
+        messages: &[ChatMessage],
+    ) -> rusqlite::Result<()> {
+        for session in sessions {
+            self.save_chat_session(session)?;
+        }
+        for message in messages {
+            self.insert_chat_message(message)?;
+        }
+        Ok(())
+    }
+
+    fn transcript(&self, session_id: &str) -> Result<Transcript, String> {
+        let session = self
+            .chat_session(session_id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("chat session {session_id} does not exist"))?;
+        let messages = self
+            .chat_session_messages(session_id)
+            .map_err(|e| e.to_string())?;
+        Ok(Transcript { session, messages })
+    }
+}
+
+/// A file name derived from the session title, for the save dialog.
+fn default_file_name(transcripts: &[Transcript], format: TranscriptFormat) -> String {
+    let stem = match transcripts {
+        [transcript] => transcript
+            .session
+            .title
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect::<String>()
+            .split('-')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-"),
+        _ => String::new(),
+    };
+    let stem = if stem.is_empty() { "esaf-chats" } else { &stem };
+    format!("{stem}.{}", format.extension())
+}
+
+fn read_transcripts(path: &Path, format: Option<ImportFormat>) -> Result<Vec<Transcript>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let format = format.unwrap_or_else(|| ImportFormat::detect(&text));
+    let transcripts = match format {
+        ImportFormat::Jsonl => parse_jsonl(&text),
+        ImportFormat::Openai => parse_openai(&text),
+    };
+    transcripts.map_err(|e| format!("{} is not a readable transcript: {e}", path.display()))
+}
+
+/// Exports the given sessions, or every session when none are given, to
+/// `path` in `format`, asking the user for a location when no path is
+/// given. Returns the written path, or `None` if the user cancelled the
+/// dialog.
+#[tauri::command]
+pub async fn export_chat_sessions(
+    session_ids: Vec<String>,
+    format: TranscriptFormat,
+    options: Option<ExportOptions>,
+    path: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+) -> Result<Option<String>, String> {
+    let transcripts = {
+        let app_state = state.lock().map_err(|e| e.to_string())?;
+        let session_ids = if session_ids.is_empty() {
+            app_state
+                .store
+                .chat_sessions()
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|session| session.id)
+                .collect()
+        } else {
+            session_ids
+        };
+        session_ids
+            .iter()
+            .map(|session_id| app_state.store.transcript(session_id))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let chosen = app
+                .dialog()
+                .file()
+                .add_filter(format.filter_name(), &[format.extension()])
+                .set_file_name(default_file_name(&transcripts, format))
+                .blocking_save_file();
+            match chosen {
+                Some(chosen) => chosen.into_path().map_err(|e| e.to_string())?,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let options = options.unwrap_or_default();
+    let rendered = match format {
+        TranscriptFormat::Markdown => render_markdown(&transcripts, &options),
+        TranscriptFormat::Jsonl => render_jsonl(&transcripts, &options)?,
+        TranscriptFormat::Html => render_html(&transcripts, &options),
+    };
+    fs::write(&path, rendered).map_err(|e| e.to_string())?;
+    Ok(Some(path.display().to_string()))
+}
+
+/// Imports every conversation in the transcript at `path` as a new session,
+/// asking the user for a file when no path is given. The format is detected
+/// from the content unless given. Returns the new sessions, or `None` if the
+/// user cancelled the dialog.
+#[tauri::command]
+pub async fn import_chat_sessions(
+    path: Option<String>,
+    format: Option<ImportFormat>,
+    app: AppHandle,
+    state: State<'_, AppStateType>,
+) -> Result<Option<Vec<ChatSession>>, String> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let chosen = app
+                .dialog()
+                .file()
+                .add_filter("Chat transcripts", &["jsonl", "json"])
+                .blocking_pick_file();
+            match chosen {
+                Some(chosen) => chosen.into_path().map_err(|e| e.to_string())?,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let transcripts = read_transcripts(&path, format)?;
+    let mut sessions = Vec::new();
+    let mut messages = Vec::new();
+    for transcript in transcripts {
+        sessions.push(transcript.session);
+        messages.extend(transcript.messages);
+    }
+
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.commit(Mutation::ImportChatSessions {
+        sessions: sessions.clone(),
+        messages,
+    })?;
+    Ok(Some(sessions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(
+        session_id: &str,
+        message_type: ChatMessageType,
+        content: &str,
+        timestamp: i64,
+    ) -> ChatMessage {
+        ChatMessage {
+            id: Uuid::new_v4().to_string(),
+            message_type,
+            content: content.to_string(),
+            timestamp,
+            agent_name: (message_type == ChatMessageType::Agent).then(|| "data-agent".to_string()),
+            metadata: None,
+            session_id: session_id.to_string(),
+        }
+    }
+
+    /// A session whose user message is markup, followed by the agent's
+    /// thinking and its answer.
+    fn transcript() -> Transcript {
+        let mut session = ChatSession::new("Sales <Q3> & more".to_string());
+        session.created = 60_000;
+        session.last_activity = 180_000;
+        session.tags = vec!["sales".to_string()];
+        let messages = vec![
+            message(
+                &session.id,
+                ChatMessageType::User,
+                "Chart <script>alert(\"x\")</script> & 'y'",
+                60_000,
+            ),
+            message(
+                &session.id,
+                ChatMessageType::Thinking,
+                "Weighing it",
+                120_000,
+            ),
+            message(&session.id, ChatMessageType::Agent, "Here it is", 180_000),
+        ];
+        Transcript { session, messages }
+    }
+
+    #[test]
+    fn detects_openai_documents_and_falls_back_to_jsonl() {
+        assert_eq!(ImportFormat::detect("[]"), ImportFormat::Openai);
+        assert_eq!(
+            ImportFormat::detect(r#"{"mapping": {}}"#),
+            ImportFormat::Openai
+        );
+        assert_eq!(
+            ImportFormat::detect(r#"{"messages": []}"#),
+            ImportFormat::Openai
+        );
+        // A one-line JSON Lines file is a single object without either key.
+        assert_eq!(
+            ImportFormat::detect(r#"{"id": "m1", "sessionId": "s1"}"#),
+            ImportFormat::Jsonl
+        );
+        assert_eq!(
+            ImportFormat::detect("{\"id\": \"m1\"}\n{\"id\": \"m2\"}\n"),
+            ImportFormat::Jsonl
+        );
+    }
+
+    #[test]
+    fn jsonl_groups_lines_by_session_and_reports_malformed_lines() {
+        let line = |session: &str, title: Option<&str>, content: &str, timestamp: i64| {
+            json!({
+                "id": "m",
+                "type": "user",
+                "content": content,
+                "timestamp": timestamp,
+                "sessionId": session,
+                "sessionTitle": title,
+            })
+            .to_string()
+        };
+        let text = [
+            line("a", Some("First"), "one", 10),
+            line("b", None, "Second question", 20),
+            String::new(),
+            line("a", None, "two", 30),
+        ]
+        .join("\n");
+
+        let transcripts = parse_jsonl(&text).unwrap();
+        assert_eq!(transcripts.len(), 2);
+        let (first, second) = (&transcripts[0], &transcripts[1]);
+        assert_eq!(first.session.title, "First");
+        assert_eq!(
+            (first.session.created, first.session.last_activity),
+            (10, 30)
+        );
+        assert_eq!(first.session.message_count, 2);
+        let contents: Vec<_> = first.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["one", "two"]);
+        assert_ne!(first.messages[0].id, first.messages[1].id);
+        assert!(first
+            .messages
+            .iter()
+            .all(|m| m.session_id == first.session.id));
+        // Without a title, the session is named after its first question.
+        assert_eq!(second.session.title, title_from_message("Second question"));
+
+        let broken = format!("{}\nnot json\n", line("a", None, "one", 10));
+        let err = parse_jsonl(&broken).unwrap_err();
+        assert!(err.starts_with("line 2:"), "{err}");
+    }
+
+    #[test]
+    fn chatgpt_exports_follow_the_current_branch() {
+        let node = |parent: Option<&str>, children: &[&str], role: &str, text: &str| {
+            json!({
+                "parent": parent,
+                "children": children,
+                "message": {
+                    "author": { "role": role },
+                    "content": { "content_type": "text", "parts": [text] },
+                    "metadata": { "model_slug": "gpt-4o" },
+                },
+            })
+        };
+        let mut conversation = json!({
+            "title": "Branches",
+            "create_time": 1000.0,
+            "mapping": {
+                "root": { "parent": null, "children": ["q"], "message": null },
+                "q": node(Some("root"), &["old", "new"], "user", "Question"),
+                "old": node(Some("q"), &[], "assistant", "First answer"),
+                "new": node(Some("q"), &[], "assistant", "Regenerated answer"),
+            },
+            "current_node": "old",
+        });
+
+        let contents = |conversation: &Value| {
+            let transcripts = parse_openai(&conversation.to_string()).unwrap();
+            assert_eq!(transcripts.len(), 1);
+            assert_eq!(transcripts[0].session.title, "Branches");
+            transcripts[0]
+                .messages
+                .iter()
+                .map(|m| m.content.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(contents(&conversation), ["Question", "First answer"]);
+
+        conversation["current_node"] = json!("new");
+        assert_eq!(contents(&conversation), ["Question", "Regenerated answer"]);
+        let branch = chatgpt_branch(&conversation);
+        assert_eq!(branch.len(), 2);
+        let answer = openai_message(branch[1], 0).unwrap();
+        assert_eq!(answer.message_type, ChatMessageType::Agent);
+        assert_eq!(answer.agent_name.as_deref(), Some("gpt-4o"));
+        // Messages without their own time take the conversation's.
+        assert_eq!(answer.timestamp, 0);
+        assert_eq!(
+            parse_openai(&conversation.to_string()).unwrap()[0]
+                .session
+                .created,
+            1_000_000
+        );
+
+        let err = parse_openai(r#"[{"title": "empty"}]"#).unwrap_err();
+        assert_eq!(err, "conversation 1 has neither a mapping nor messages");
+    }
+
+    #[test]
+    fn jsonl_exports_import_back_as_new_sessions() {
+        let original = transcript();
+        let options = ExportOptions {
+            include_thinking: true,
+        };
+        let text = render_jsonl(std::slice::from_ref(&original), &options).unwrap();
+        assert_eq!(ImportFormat::detect(&text), ImportFormat::Jsonl);
+
+        let imported = parse_jsonl(&text).unwrap();
+        assert_eq!(imported.len(), 1);
+        let imported = &imported[0];
+        assert_ne!(imported.session.id, original.session.id);
+        assert_eq!(imported.session.title, original.session.title);
+        assert_eq!(imported.session.created, original.session.created);
+        assert_eq!(
+            imported.session.last_activity,
+            original.session.last_activity
+        );
+        assert_eq!(imported.messages.len(), original.messages.len());
+        for (imported, original) in imported.messages.iter().zip(&original.messages) {
+            assert_eq!(imported.message_type, original.message_type);
+            assert_eq!(imported.content, original.content);
+            assert_eq!(imported.timestamp, original.timestamp);
+            assert_eq!(imported.agent_name, original.agent_name);
+        }
+
+        // Thinking is left out unless asked for.
+        let text = render_jsonl(&[original], &ExportOptions::default()).unwrap();
+        assert_eq!(parse_jsonl(&text).unwrap()[0].messages.len(), 2);
+    }
+
+    #[test]
+    fn markdown_exports_every_visible_message_in_order() {
+        let transcript = transcript();
+        let markdown =
+            render_markdown(std::slice::from_ref(&transcript), &ExportOptions::default());
+        assert!(markdown.starts_with("# Sales <Q3> & more\n\n"));
+        assert!(markdown.contains("_1970-01-01 00:01 UTC to 1970-01-01 00:03 UTC_"));
+        assert!(markdown.contains("Tags: sales"));
+        let user = markdown.find("### User · 1970-01-01 00:01 UTC").unwrap();
+        let agent = markdown
+            .find("### data-agent · 1970-01-01 00:03 UTC")
+            .unwrap();
+        assert!(user < agent);
+        assert!(markdown.contains("Chart <script>alert(\"x\")</script> & 'y'\n\n"));
+        assert!(!markdown.contains("Weighing it"));
+
+        let options = ExportOptions {
+            include_thinking: true,
+        };
+        let markdown = render_markdown(&[transcript], &options);
+        assert!(markdown.contains("> Weighing it\n\n"));
+    }
+
+    #[test]
+    fn html_escapes_titles_and_message_content() {
+        let html = render_html(&[transcript()], &ExportOptions::default());
+        assert!(html.contains("<title>Sales &lt;Q3&gt; &amp; more</title>"));
+        assert!(html.contains("<h1>Sales &lt;Q3&gt; &amp; more</h1>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(
+            "<div class=\"content\">Chart &lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; \
+             &amp; &#39;y&#39;</div>"
+        ));
+        assert!(html.contains("<article class=\"message agent\">"));
+    }
+}
@@ -0,0 +1,499 @@
+use crate::settings::LlmProvider;
+use crate::AppStateType;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+use zeroize::Zeroizing;
+
+/// Encrypted secrets in the app data directory, shared by all workspaces.
+pub const VAULT_FILE: &str = "vault.json";
+
+/// Marker identifying ESAF vault files.
+const VAULT_FORMAT: &str = "esaf-vault";
+
+const VAULT_VERSION: u32 = 1;
+
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+const KEY_LEN: usize = 32;
+
+const SALT_LEN: usize = 16;
+
+const NONCE_LEN: usize = 12;
+
+/// Plaintext sealed under the vault key to tell a wrong passphrase apart
+/// from a damaged secret.
+const VERIFIER: &[u8] = b"esaf-vault-verifier";
+
+/// Argon2id parameters, stored with the vault so they can be raised later
+/// without breaking existing vaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KdfParams {
+    algorithm: String,
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    fn new() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams {
+            algorithm: "argon2id".to_string(),
+            salt: hex::encode(salt),
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<Zeroizing<[u8; KEY_LEN]>, String> {
+        if self.algorithm != "argon2id" {
+            return Err(format!("unsupported key derivation {}", self.algorithm));
+        }
+        let salt = hex::decode(&self.salt).map_err(|e| format!("vault salt is corrupt: {e}"))?;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(|e| e.to_string())?;
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| e.to_string())?;
+        Ok(key)
+    }
+}
+
+/// A value encrypted with ChaCha20-Poly1305. The secret's name is bound in
+/// as associated data, so entries cannot be swapped between names.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Sealed {
+    fn seal(key: &[u8; KEY_LEN], name: &str, plaintext: &[u8]) -> Result<Self, String> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .map_err(|_| format!("failed to encrypt {name}"))?;
+        Ok(Sealed {
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    fn open(&self, key: &[u8; KEY_LEN], name: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+        let nonce = hex::decode(&self.nonce)
+            .ok()
+            .filter(|nonce| nonce.len() == NONCE_LEN)
+            .ok_or_else(|| format!("nonce of {name} is corrupt"))?;
+        let ciphertext = hex::decode(&self.ciphertext).map_err(|_| format!("{name} is corrupt"))?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+        cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| format!("{name} could not be decrypted"))
+    }
+}
+
+/// On-disk layout of `VAULT_FILE`. Secret names are stored in the clear;
+/// only values are encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultFile {
+    format: String,
+    version: u32,
+    kdf: KdfParams,
+    verifier: Sealed,
+    secrets: BTreeMap<String, Sealed>,
+}
+
+/// Whether a vault exists and whether its key is currently held in memory.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultStatus {
+    pub initialized: bool,
+    pub unlocked: bool,
+}
+
+/// Passphrase-protected store for API keys and other secrets. Values never
+/// leave the backend; commands only reveal secret names.
+pub struct Vault {
+    path: PathBuf,
+    file: Option<VaultFile>,
+    key: Option<Zeroizing<[u8; KEY_LEN]>>,
+}
+
+impl Vault {
+    /// Loads the vault under `root`, locked. A missing file means no vault
+    /// has been created yet.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let path = root.join(VAULT_FILE);
+        let file = if path.exists() {
+            let json = fs::read(&path).map_err(|e| e.to_string())?;
+            let file: VaultFile = serde_json::from_slice(&json)
+                .map_err(|e| format!("{} is corrupt: {e}", path.display()))?;
+            if file.format != VAULT_FORMAT {
+                return Err(format!("{} is not an ESAF vault", path.display()));
+            }
+            if file.version > VAULT_VERSION {
+                return Err(format!(
+                    "vault version {} is newer than the supported version {VAULT_VERSION}",
+                    file.version
+                ));
+            }
+            Some(file)
+        } else {
+            None
+        };
+        Ok(Vault {
+            path,
+            file,
+            key: None,
+        })
+    }
+
+    pub fn status(&self) -> VaultStatus {
+        VaultStatus {
+            initialized: self.file.is_some(),
+            unlocked: self.key.is_some(),
+        }
+    }
+
+    /// Creates an empty vault protected by `passphrase` and leaves it
+    /// unlocked.
+    pub fn create(&mut self, passphrase: &str) -> Result<(), String> {
+        if self.file.is_some() {
+            return Err("a vault already exists".to_string());
+        }
+        if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+            return Err(format!(
+                "passphrase must be at least {MIN_PASSPHRASE_LEN} characters"
+            ));
+        }
+
+        let kdf = KdfParams::new();
+        let key = kdf.derive(passphrase)?;
+        let file = VaultFile {
+            format: VAULT_FORMAT.to_string(),
+            version: VAULT_VERSION,
+            verifier: Sealed::seal(&key, VAULT_FORMAT, VERIFIER)?,
+            kdf,
+            secrets: BTreeMap::new(),
+        };
+        save(&self.path, &file)?;
+        self.file = Some(file);
+        self.key = Some(key);
+        Ok(())
+    }
+
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), String> {
+        let file = self.require_file()?;
+        let key = file.kdf.derive(passphrase)?;
+        match file.verifier.open(&key, VAULT_FORMAT) {
+            Ok(verifier) if verifier.as_slice() == VERIFIER => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err("incorrect passphrase".to_string()),
+        }
+    }
+
+    /// Drops the key from memory. Secrets stay on disk.
+    pub fn lock(&mut self) {
+        self.key = None;
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.file
+            .as_ref()
+            .map(|file| file.secrets.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        check_name(name)?;
+        let key = self.require_key()?;
+        let sealed = Sealed::seal(key, name, value.as_bytes())?;
+        let mut file = self.require_file()?.clone();
+        file.secrets.insert(name.to_string(), sealed);
+        save(&self.path, &file)?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        self.require_key()?;
+        let mut file = self.require_file()?.clone();
+        if file.secrets.remove(name).is_none() {
+            return Err(format!("secret {name} does not exist"));
+        }
+        save(&self.path, &file)?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Decrypts the secret `name`. For backend use only; never hand the
+    /// result to the webview.
+    pub fn secret(&self, name: &str) -> Result<Option<Zeroizing<String>>, String> {
+        let Some(sealed) = self.require_file()?.secrets.get(name) else {
+            return Ok(None);
+        };
+        let plaintext = sealed.open(self.require_key()?, name)?;
+        let value = String::from_utf8(plaintext.to_vec())
+            .map_err(|_| format!("secret {name} is not valid UTF-8"))?;
+        Ok(Some(Zeroizing::new(value)))
+    }
+
+    /// API key for `provider`: the vault entry named after the provider,
+    /// falling back to its `ESAF_*_API_KEY` environment variable. A locked
+    /// vault only counts as an error if it actually holds the key.
+    pub fn provider_api_key(
+        &self,
+        provider: LlmProvider,
+    ) -> Result<Option<Zeroizing<String>>, String> {
+        let name = provider.as_str();
+        if self.names().iter().any(|stored| stored == name) {
+            return self.secret(name);
+        }
+        Ok(std::env::var(provider.api_key_variable())
+            .ok()
+            .filter(|key| !key.is_empty())
+            .map(Zeroizing::new))
+    }
+
+    fn require_file(&self) -> Result<&VaultFile, String> {
+        self.file
+            .as_ref()
+            .ok_or_else(|| "no vault has been created".to_string())
+    }
+
+    fn require_key(&self) -> Result<&[u8; KEY_LEN], String> {
+        self.require_file()?;
+        self.key
+            .as_deref()
+            .ok_or_else(|| "the vault is locked".to_string())
+    }
+}
+
+/// Secret names are shown in the UI and used as associated data, so they
+/// are kept to a plain identifier alphabet.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "secret name {name:?} must be 1-64 letters, digits, '-', '_' or '.'"
+        ))
+    }
+}
+
+/// Writes the vault through a temporary file, so a crash never leaves it
+/// half written.
+fn save(path: &Path, file: &VaultFile) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(file).map_err(|e| e.to_string())?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    fs::write(&partial, json).map_err(|e| e.to_string())?;
+    fs::rename(&partial, path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_vault_status(state: State<AppStateType>) -> Result<VaultStatus, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    Ok(app_state.vault.status())
+}
+
+#[tauri::command]
+pub fn create_vault(passphrase: String, state: State<AppStateType>) -> Result<VaultStatus, String> {
+    let passphrase = Zeroizing::new(passphrase);
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.vault.create(&passphrase)?;
+    Ok(app_state.vault.status())
+}
+
+#[tauri::command]
+pub fn unlock_vault(passphrase: String, state: State<AppStateType>) -> Result<VaultStatus, String> {
+    let passphrase = Zeroizing::new(passphrase);
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.vault.unlock(&passphrase)?;
+    Ok(app_state.vault.status())
+}
+
+#[tauri::command]
+pub fn lock_vault(state: State<AppStateType>) -> Result<VaultStatus, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.vault.lock();
+    Ok(app_state.vault.status())
+}
+
+#[tauri::command]
+pub fn list_secret_names(state: State<AppStateType>) -> Result<Vec<String>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    Ok(app_state.vault.names())
+}
+
+/// Stores `value` under `name`, replacing any previous value. Requires an
+/// unlocked vault.
+#[tauri::command]
+pub fn set_secret(name: String, value: String, state: State<AppStateType>) -> Result<(), String> {
+    let value = Zeroizing::new(value);
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.vault.set(&name, &value)
+}
+
+#[tauri::command]
+pub fn delete_secret(name: String, state: State<AppStateType>) -> Result<(), String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.vault.delete(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn unlocked_vault(root: &Path) -> Vault {
+        let mut vault = Vault::load(root).unwrap();
+        vault.create(PASSPHRASE).unwrap();
+        vault
+    }
+
+    #[test]
+    fn secrets_survive_locking_and_reloading() {
+        let root = temp_dir("vault");
+        let mut vault = Vault::load(&root).unwrap();
+        assert!(!vault.status().initialized);
+        assert!(vault.create("short").is_err());
+        vault.create(PASSPHRASE).unwrap();
+        vault.set("openai", "sk-live").unwrap();
+
+        vault.lock();
+        let error = vault.provider_api_key(LlmProvider::Openai).unwrap_err();
+        assert_eq!(error, "the vault is locked");
+        assert!(vault.set("anthropic", "ak").is_err());
+
+        vault.unlock(PASSPHRASE).unwrap();
+        let key = vault
+            .provider_api_key(LlmProvider::Openai)
+            .unwrap()
+            .unwrap();
+        assert_eq!(key.as_str(), "sk-live");
+
+        let mut reloaded = Vault::load(&root).unwrap();
+        assert!(reloaded.status().initialized && !reloaded.status().unlocked);
+        reloaded.unlock(PASSPHRASE).unwrap();
+        let key = reloaded.secret("openai").unwrap().unwrap();
+        assert_eq!(key.as_str(), "sk-live");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn a_wrong_passphrase_is_rejected() {
+        let root = temp_dir("vault");
+        let mut vault = unlocked_vault(&root);
+        vault.lock();
+
+        let error = vault.unlock("wrong horse battery").unwrap_err();
+        assert_eq!(error, "incorrect passphrase");
+        assert!(!vault.status().unlocked);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn names_are_listed_without_their_values() {
+        let root = temp_dir("vault");
+        let mut vault = unlocked_vault(&root);
+        vault.set("openai", "sk-first-value").unwrap();
+        vault.set("anthropic", "ak-second-value").unwrap();
+        assert!(vault.set("not a name", "x").is_err());
+        vault.lock();
+
+        assert_eq!(vault.names(), ["anthropic", "openai"]);
+        let file = fs::read_to_string(root.join(VAULT_FILE)).unwrap();
+        assert!(file.contains("\"openai\""));
+        assert!(!file.contains("first-value") && !file.contains("second-value"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn a_secret_only_opens_under_its_own_name() {
+        let root = temp_dir("vault");
+        let mut vault = unlocked_vault(&root);
+        vault.set("openai", "sk-live").unwrap();
+
+        // Copy the sealed OpenAI key over to another name, as someone
+        // editing the file might.
+        let file = vault.file.as_mut().unwrap();
+        let sealed = file.secrets["openai"].clone();
+        file.secrets.insert("anthropic".to_string(), sealed);
+
+        let error = vault.secret("anthropic").unwrap_err();
+        assert_eq!(error, "anthropic could not be decrypted");
+        assert_eq!(vault.secret("openai").unwrap().unwrap().as_str(), "sk-live");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn keys_fall_back_to_the_environment() {
+        let root = temp_dir("vault");
+        let variable = LlmProvider::LmStudio.api_key_variable();
+        std::env::set_var(&variable, "lm-from-env");
+
+        let mut vault = Vault::load(&root).unwrap();
+        let key = vault.provider_api_key(LlmProvider::LmStudio).unwrap();
+        assert_eq!(key.as_deref().map(String::as_str), Some("lm-from-env"));
+
+        vault.create(PASSPHRASE).unwrap();
+        vault.lock();
+        let key = vault.provider_api_key(LlmProvider::LmStudio).unwrap();
+        assert_eq!(key.as_deref().map(String::as_str), Some("lm-from-env"));
+
+        vault.unlock(PASSPHRASE).unwrap();
+        vault.set("lm-studio", "lm-from-vault").unwrap();
+        let key = vault.provider_api_key(LlmProvider::LmStudio).unwrap();
+        assert_eq!(key.as_deref().map(String::as_str), Some("lm-from-vault"));
+
+        std::env::remove_var(&variable);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use crate::journal::{self, Journal};
+use crate::settings;
+use crate::storage;
+use crate::store::{self, now_millis, Store};
+use crate::AppStateType;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, State};
+use uuid::Uuid;
+
+/// Directory under the app data directory holding one directory per
+/// workspace.
+pub const WORKSPACES_DIR: &str = "workspaces";
+
+/// Registry of workspaces and the active one, next to `WORKSPACES_DIR`.
+pub const REGISTRY_FILE: &str = "workspaces.json";
+
+/// Name of the workspace created on first start.
+pub const DEFAULT_WORKSPACE_NAME: &str = "Default";
+
+/// Tauri event emitted with the new active [`Workspace`] after a switch.
+pub const WORKSPACE_CHANNEL: &str = "esaf-workspace";
+
+/// Files that made up the single global state before workspaces existed.
+const LEGACY_FILES: &[&str] = &["esaf.db", "esaf.db-wal", "esaf.db-shm", "journal.log"];
+
+/// A named, isolated set of tasks, agents, events, results, chats, documents
+/// and settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    pub created_at: i64,
+}
+
+impl Workspace {
+    fn new(name: &str) -> Self {
+        Workspace {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            created_at: now_millis(),
+        }
+    }
+}
+
+/// The known workspaces and which one is active, persisted as
+/// `REGISTRY_FILE`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceRegistry {
+    #[serde(skip)]
+    root: PathBuf,
+    active: String,
+    workspaces: Vec<Workspace>,
+}
+
+impl WorkspaceRegistry {
+    /// Loads the registry under `root`, creating a default workspace on first
+    /// start. State from before workspaces existed is moved into it.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let path = root.join(REGISTRY_FILE);
+        if path.exists() {
+            let json = fs::read(&path).map_err(|e| e.to_string())?;
+            let mut registry: WorkspaceRegistry = serde_json::from_slice(&json)
+                .map_err(|e| format!("{} is corrupt: {e}", path.display()))?;
+            registry.root = root.to_path_buf();
+            if registry.workspace(&registry.active).is_none() {
+                return Err(format!(
+                    "active workspace {} is missing from {}",
+                    registry.active,
+                    path.display()
+                ));
+            }
+            return Ok(registry);
+        }
+
+        let workspace = Workspace::new(DEFAULT_WORKSPACE_NAME);
+        let registry = WorkspaceRegistry {
+            root: root.to_path_buf(),
+            active: workspace.id.clone(),
+            workspaces: vec![workspace],
+        };
+        let dir = registry.dir(&registry.active);
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        for file in LEGACY_FILES {
+            let legacy = root.join(file);
+            if legacy.exists() {
+                fs::rename(&legacy, dir.join(file)).map_err(|e| e.to_string())?;
+            }
+        }
+        registry.save()?;
+        Ok(registry)
+    }
+
+    /// Writes the registry through a temporary file, so a crash never leaves
+    /// it half written.
+    fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        let path = self.root.join(REGISTRY_FILE);
+        let partial = self.root.join(format!("{REGISTRY_FILE}.partial"));
+        fs::write(&partial, json).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())
+    }
+
+    /// The app data directory holding the registry and all workspaces.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn dir(&self, workspace_id: &str) -> PathBuf {
+        self.root.join(WORKSPACES_DIR).join(workspace_id)
+    }
+
+    /// Directory of the active workspace.
+    pub fn active_dir(&self) -> PathBuf {
+        self.dir(&self.active)
+    }
+
+    pub fn active(&self) -> &Workspace {
+        self.workspace(&self.active)
+            .expect("the active workspace is always registered")
+    }
+
+    pub fn workspaces(&self) -> &[Workspace] {
+        &self.workspaces
+    }
+
+    fn workspace(&self, workspace_id: &str) -> Option<&Workspace> {
+        self.workspaces
+            .iter()
+            .find(|workspace| workspace.id == workspace_id)
+    }
+
+    fn require(&self, workspace_id: &str) -> Result<&Workspace, String> {
+        self.workspace(workspace_id)
+            .ok_or_else(|| format!("workspace {workspace_id} does not exist"))
+    }
+
+    /// Trims `name` and checks that no other workspace already uses it.
+    fn check_name(&self, name: &str, except: Option<&str>) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("workspace name must not be empty".to_string());
+        }
+        if self
+            .workspaces
+            .iter()
+            .any(|workspace| workspace.name == name && Some(workspace.id.as_str()) != except)
+        {
+            return Err(format!("a workspace named {name} already exists"));
+        }
+        Ok(name.to_string())
+    }
+}
+
+/// Opens the store and journal of the workspace in `dir`, replaying what the
+/// journal holds beyond the store and indexing anything not yet searchable.
+pub fn open_workspace(dir: &Path) -> Result<(Store, Journal), String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let store = Store::open(&dir.join(store::DATABASE_FILE)).map_err(|e| e.to_string())?;
+    let applied_seq = store.applied_seq().map_err(|e| e.to_string())?;
+    let (mut journal, pending) =
+        Journal::open(&dir.join(journal::JOURNAL_FILE), applied_seq).map_err(|e| e.to_string())?;
+    store.replay(&pending).map_err(|e| e.to_string())?;
+    journal.compact(&store)?;
+    store.backfill_search_index()?;
+    Ok((store, journal))
+}
+
+#[tauri::command]
+pub fn list_workspaces(state: State<AppStateType>) -> Result<Vec<Workspace>, String> {
+    let app_state = state.lock().map_err(|e| e.to_string())?;
+    Ok(app_state.workspaces.workspaces().to_vec())
+}
+
+#[tauri::command]
+pub fn create_workspace(name: String, state: State<AppStateType>) -> Result<Workspace, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let name = app_state.workspaces.check_name(&name, None)?;
+    let workspace = Workspace::new(&name);
+
+    let dir = app_state.workspaces.dir(&workspace.id);
+    // Opening once lays down the schema, so the directory is complete.
+    open_workspace(&dir)?;
+    app_state.workspaces.workspaces.push(workspace.clone());
+    if let Err(e) = app_state.workspaces.save() {
+        app_state.workspaces.workspaces.pop();
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(workspace)
+}
+
+#[tauri::command]
+pub fn rename_workspace(
+    workspace_id: String,
+    name: String,
+    state: State<AppStateType>,
+) -> Result<Workspace, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.workspaces.require(&workspace_id)?;
+    let name = app_state
+        .workspaces
+        .check_name(&name, Some(&workspace_id))?;
+
+    let mut renamed = app_state.workspaces.clone();
+    let workspace = renamed
+        .workspaces
+        .iter_mut()
+        .find(|workspace| workspace.id == workspace_id)
+        .expect("workspace was checked above");
+    workspace.name = name;
+    let workspace = workspace.clone();
+    renamed.save()?;
+    app_state.workspaces = renamed;
+    Ok(workspace)
+}
+
+/// Deletes a workspace and everything stored in it. The active workspace
+/// cannot be deleted; switch away from it first.
+#[tauri::command]
+pub fn delete_workspace(workspace_id: String, state: State<AppStateType>) -> Result<(), String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    app_state.workspaces.require(&workspace_id)?;
+    if app_state.workspaces.active == workspace_id {
+        return Err(format!(
+            "workspace {workspace_id} is active; switch to another workspace first"
+        ));
+    }
+
+    let mut remaining = app_state.workspaces.clone();
+    remaining
+        .workspaces
+        .retain(|workspace| workspace.id != workspace_id);
+    remaining.save()?;
+    let dir = app_state.workspaces.dir(&workspace_id);
+    app_state.workspaces = remaining;
+
+    // The workspace is already unregistered; a leftover directory is only
+    // wasted space.
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        log::warn!("failed to delete {}: {e}", dir.display());
+    }
+    Ok(())
+}
+
+/// Makes `workspace_id` the active workspace. The current workspace stays
+/// active if the new one cannot be opened.
+#[tauri::command]
+pub fn switch_workspace(
+    workspace_id: String,
+    app: AppHandle,
+    state: State<AppStateType>,
+) -> Result<Workspace, String> {
+    let mut app_state = state.lock().map_err(|e| e.to_string())?;
+    let workspace = app_state.workspaces.require(&workspace_id)?.clone();
+    if app_state.workspaces.active == workspace_id {
+        return Ok(workspace);
+    }
+
+    let (store, journal) = open_workspace(&app_state.workspaces.dir(&workspace_id))?;
+    let mut switched = app_state.workspaces.clone();
+    switched.active = workspace_id;
+    switched.save()?;
+
+    app_state.store = store;
+    app_state.journal = journal;
+    app_state.workspaces = switched;
+    app.emit(WORKSPACE_CHANNEL, &workspace)
+        .map_err(|e| e.to_string())?;
+    settings::emit_settings(&app, &app_state)?;
+    storage::refresh_quota(&app, &mut app_state)?;
+    Ok(workspace)
+}